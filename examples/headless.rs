@@ -0,0 +1,20 @@
+// Runs a small tank without a window and prints it as it settles.
+// cargo run --example headless --no-default-features
+use falling_sand::Tank;
+
+fn main() {
+    let mut tank = Tank::new(24, 12, 1);
+    for column in [3, 4, 5, 12, 19, 20] {
+        tank.drop_sand_in_column(column, 'S');
+    }
+
+    for frame in 0..40 {
+        tank.advance_frame();
+        if frame % 2 == 1 {
+            tank.drop_sand_in_column(12, 'Y');
+        }
+    }
+
+    println!("{}", tank);
+    println!("{} bytes saved as binary, {} as text", tank.to_bytes().len(), tank.to_text().len());
+}
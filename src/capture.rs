@@ -0,0 +1,90 @@
+use crate::glass_tank::{Tank, EMPTY};
+use crate::palette;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+// Empty cells are drawn black like the window's background, so captures don't come out see-through
+pub const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+
+// The tank as RGBA bytes straight from its cells, each cell `scale` pixels across
+pub fn tank_pixels(tank: &Tank, scale: usize) -> Vec<u8> {
+    let scale = scale.max(1);
+    let row_bytes = tank.width() * scale * 4;
+    let mut pixels = Vec::with_capacity(row_bytes * tank.height() * scale);
+
+    for row in tank.rows() {
+        let start = pixels.len();
+        for cell in row {
+            let color = if *cell == EMPTY { BACKGROUND } else { palette::color_of(*cell) };
+            for _ in 0..scale {
+                pixels.extend_from_slice(&color);
+            }
+        }
+        for _ in 1..scale {
+            pixels.extend_from_within(start..start + row_bytes);
+        }
+    }
+    pixels
+}
+
+pub fn write_png(path: &Path, width: usize, height: usize, rgba: &[u8]) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(rgba).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+// The first of `prefix-0001.extension`, `prefix-0002.extension`, ... in a
+// folder that doesn't exist yet, so nothing gets overwritten. An empty
+// extension gives names without one, for folders
+pub fn numbered_path(folder: &Path, prefix: &str, extension: &str) -> PathBuf {
+    let dot = if extension.is_empty() { "" } else { "." };
+    (1..).map(|number| folder.join(format!("{}-{:04}{}{}", prefix, number, dot, extension)))
+        .find(|path| !path.exists())
+        .unwrap_or_else(|| folder.join(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_folder;
+
+    #[test]
+    fn test_tank_pixels_are_scaled_up() {
+        let t = Tank::from_grains(vec!("S-".to_string()));
+        let pixels = tank_pixels(&t, 2);
+        let beige = palette::color_of(b'S');
+
+        let expected: Vec<u8> = [beige, beige, BACKGROUND, BACKGROUND, beige, beige, BACKGROUND, BACKGROUND].concat();
+        assert!(pixels == expected, "unexpected pixels {:?}", pixels);
+    }
+
+    #[test]
+    fn test_numbered_paths_skip_existing_files() {
+        let folder = scratch_folder("numbered");
+        let first = numbered_path(&folder, "shot", "png");
+        assert!(first == folder.join("shot-0001.png"));
+
+        std::fs::write(&first, b"").unwrap();
+        assert!(numbered_path(&folder, "shot", "png") == folder.join("shot-0002.png"));
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_png_is_written() {
+        let folder = scratch_folder("png");
+        let t = Tank::from_grains(vec!("-S".to_string(), "YY".to_string()));
+        let path = folder.join("tank.png");
+        write_png(&path, 6, 6, &tank_pixels(&t, 3)).unwrap();
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        assert!(reader.info().width == 6 && reader.info().height == 6);
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
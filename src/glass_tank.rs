@@ -1,140 +1,439 @@
+mod binary_format;
+mod brush;
+mod cells;
+mod checkerboard;
+mod error;
+mod grain_names;
+mod history;
+mod region;
+mod shapes;
+mod text_format;
+
+pub use binary_format::{BinaryError, BINARY_MAGIC, BINARY_VERSION};
+pub use brush::{line_between, Brush, BrushShape, PaintMode};
+pub use cells::{Neighborhood, Position};
+pub use error::TankError;
+pub use grain_names::{grain_name, GRAIN_NAMES};
+pub use region::PasteMode;
+pub use shapes::{ellipse_cells, rectangle_cells};
+pub use text_format::{TextError, TEXT_VERSION};
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+// Cells are stored as the ASCII byte of their grain symbol, so a cell id is a
+// single byte and a tank is one contiguous buffer of `width * height` bytes
+pub type Cell = u8;
+
+pub const EMPTY: Cell = b'-';
+
+// Tanks are split into square chunks of this many cells a side. Chunks where
+// nothing changed last frame are asleep and skipped by advance_frame
+const CHUNK_SIZE: usize = 32;
+
+// Biggest tank side there can be, so a corrupt or hostile file or a bad
+// argument can't ask for more memory than any real scene needs
+pub const MAX_TANK_SIDE: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    // Every row from the bottom up, left to right across the whole tank
+    RowScan,
+    // Chunks are updated in four checkerboard passes so that no two chunks in
+    // a pass touch the same cells. Gives the same result for any thread count
+    Checkerboard { threads: usize },
+}
+
+// Which part of the old grid stays put when a tank is resized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // Where the old grid starts inside the new one, which is negative when it gets cropped
+    fn offset(self, old: (usize, usize), new: (usize, usize)) -> (isize, isize) {
+        let spare_width = new.0 as isize - old.0 as isize;
+        let spare_height = new.1 as isize - old.1 as isize;
+
+        let column = match self {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => 0,
+            Anchor::Top | Anchor::Center | Anchor::Bottom => spare_width / 2,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => spare_width
+        };
+        let row = match self {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => 0,
+            Anchor::Left | Anchor::Center | Anchor::Right => spare_height / 2,
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => spare_height
+        };
+
+        (row, column)
+    }
+}
+
 pub struct Tank {
-    pub grains: Vec<Vec<char>>,
-    step: usize
+    grains: Vec<Cell>,
+    width: usize,
+    height: usize,
+    step: usize,
+    schedule: Schedule,
+    // Number of grains stacked contiguously from the bottom of each column
+    column_heights: Vec<usize>,
+    chunks_wide: usize,
+    chunks_high: usize,
+    chunk_grain_counts: Vec<usize>,
+    // Chunks to process this frame, and chunks woken up for the next one
+    awake: Vec<bool>,
+    awake_next: Vec<bool>,
+    // Cells a grain moved into this frame, only used by the checkerboard schedule
+    moved: Vec<bool>,
+    // Started on the first checkerboard frame that uses more than one thread
+    workers: Option<checkerboard::Workers>,
+    history: history::History
 }
 
 #[derive(Debug)]
 #[derive(PartialEq)]
 enum Direction {
-    LEFT,
-    RIGHT,
-    NEITHER,
+    Left,
+    Right,
+    Neither,
 }
 
 impl Tank {
     pub fn new(width: usize, height: usize, step: usize) -> Tank {
-        assert!(width > 0, "Width must be greater than 0");
-        assert!(height > 0, "Height must be greater than 0");
-        Self {
-            grains: vec![vec!['-'; width]; height],
-            step
+        Tank::try_new(width, height, step).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_new(width: usize, height: usize, step: usize) -> Result<Tank, TankError> {
+        if width == 0 || height == 0 {
+            return Err(TankError::ZeroSize { width, height });
         }
+        let cells = width.checked_mul(height).filter(|_| width <= MAX_TANK_SIDE && height <= MAX_TANK_SIDE);
+        let Some(cells) = cells else {
+            return Err(TankError::TooLarge { width, height });
+        };
+
+        let chunks_wide = width.div_ceil(CHUNK_SIZE);
+        let chunks_high = height.div_ceil(CHUNK_SIZE);
+        Ok(Self {
+            grains: vec![EMPTY; cells],
+            width,
+            height,
+            step,
+            schedule: Schedule::RowScan,
+            column_heights: vec![0; width],
+            chunks_wide,
+            chunks_high,
+            chunk_grain_counts: vec![0; chunks_wide * chunks_high],
+            awake: vec![true; chunks_wide * chunks_high],
+            awake_next: vec![true; chunks_wide * chunks_high],
+            moved: vec![],
+            workers: None,
+            history: history::History::default()
+        })
     }
 
+    // Short rows are padded with empty cells so the grid stays rectangular,
+    // which is handy for tanks typed out in tests
     pub fn from_grains(v: Vec<String>) -> Tank {
-        assert!(v.len() > 0, "from_grains: Height must be greater than 0!");
-        assert!(v[0].len() > 0, "from_grains: Width must be greater than 0!");
-        Self {
-            grains: {
-                let mut outer: Vec<Vec<char>> = vec![];
-
-                for line in v.iter() {
-                    let mut inner: Vec<char> = vec![];
-                    for char in line.chars() {
-                        inner.push(char);
-                    }
-                    outer.push(inner);
-                }
-            
-                outer
-            },
-            step: 0
+        assert!(!v.is_empty(), "from_grains: Height must be greater than 0!");
+        assert!(!v[0].is_empty(), "from_grains: Width must be greater than 0!");
+
+        let width = v.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let padded = v.iter().map(|line| format!("{:-<width$}", line, width = width));
+        Tank::try_from_grains(padded).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Like from_grains, but every row has to be the same length
+    pub fn try_from_grains<S: AsRef<str>>(rows: impl IntoIterator<Item = S>) -> Result<Tank, TankError> {
+        let rows: Vec<S> = rows.into_iter().collect();
+        let width = rows.first().map_or(0, |row| row.as_ref().chars().count());
+        let mut tank = Tank::try_new(width, rows.len(), 0)?;
+
+        for (row, line) in rows.iter().enumerate() {
+            let found = line.as_ref().chars().count();
+            if found != width {
+                return Err(TankError::RaggedRow { row, expected: width, found });
+            }
+
+            for (column, grain) in line.as_ref().chars().enumerate() {
+                tank.set_cell(row, column, try_to_cell(grain)?);
+            }
         }
+
+        Ok(tank)
     }
 
-    pub fn advance_frame(&mut self) {
-        for row in (0..self.grains.len()).rev() {
-            let mut direction_moved = Direction::NEITHER;
-            for column in 0..self.grains[row].len() {
-                if self.grains[row][column] == '-'  || row == self.grains.len() - 1 {
-                    direction_moved = Direction::NEITHER;
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    // Changing the step changes which piles are stable, so every chunk has to look again
+    pub fn set_step(&mut self, step: usize) {
+        self.step = step;
+        self.awake_next.fill(true);
+    }
+
+    pub fn schedule(&self) -> Schedule {
+        self.schedule
+    }
+
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+
+    // Grows or shrinks the grid, keeping the grains that still fit. Everything
+    // is woken up afterwards so grains left hanging over new space fall into it.
+    // The undo history doesn't survive, since its cells no longer line up
+    pub fn resize(&mut self, width: usize, height: usize, anchor: Anchor) {
+        self.try_resize(width, height, anchor).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // Like resize, but a zero width or height is an error and leaves the tank as it was
+    pub fn try_resize(&mut self, width: usize, height: usize, anchor: Anchor) -> Result<(), TankError> {
+        let mut resized = Tank::try_new(width, height, self.step)?;
+        resized.schedule = self.schedule;
+        resized.workers = self.workers.take();
+
+        let (row_offset, column_offset) = anchor.offset((self.width, self.height), (width, height));
+        for row in 0..self.height {
+            let new_row = row as isize + row_offset;
+            if new_row < 0 || new_row >= height as isize {
+                continue;
+            }
+
+            for column in 0..self.width {
+                let new_column = column as isize + column_offset;
+                if new_column < 0 || new_column >= width as isize {
                     continue;
                 }
 
-                if direction_moved == Direction::RIGHT {
-                    // This is because if a grain moves to the right we will try to reprocess it
-                    // This prevents a grain from being processed twice in 1 frame
-                    direction_moved = Direction::NEITHER;
+                resized.set_cell(new_row as usize, new_column as usize, self.cell(row, column));
+            }
+        }
+
+        *self = resized;
+        Ok(())
+    }
+
+    // Panics outside the tank, even in release builds where index only has a debug_assert
+    pub fn grain_at(&self, row: usize, column: usize) -> char {
+        assert!(row < self.height && column < self.width, "row {} column {} is outside the {}x{} tank", row, column, self.width, self.height);
+        self.cell(row, column) as char
+    }
+
+    fn index(&self, row: usize, column: usize) -> usize {
+        debug_assert!(row < self.height && column < self.width);
+        row * self.width + column
+    }
+
+    fn cell(&self, row: usize, column: usize) -> Cell {
+        self.grains[self.index(row, column)]
+    }
+
+    fn set_cell(&mut self, row: usize, column: usize, cell: Cell) {
+        let index = self.index(row, column);
+        let old = self.grains[index];
+        if old == cell {
+            return;
+        }
+        self.grains[index] = cell;
+        self.history.record(index, old, cell);
+        self.wake_around(row, column);
+
+        if (old == EMPTY) != (cell == EMPTY) {
+            let chunk = self.chunk_of(row, column);
+            if cell == EMPTY {
+                self.chunk_grain_counts[chunk] -= 1;
+            } else {
+                self.chunk_grain_counts[chunk] += 1;
+            }
+            self.update_column_height(row, column);
+        }
+    }
+
+    fn chunk_of(&self, row: usize, column: usize) -> usize {
+        (row / CHUNK_SIZE) * self.chunks_wide + column / CHUNK_SIZE
+    }
+
+    // A grain only looks at the cells next to it and the column heights on
+    // either side, so a change wakes every chunk within one cell of it
+    fn wake_around(&mut self, row: usize, column: usize) {
+        let first_chunk_row = row.saturating_sub(1) / CHUNK_SIZE;
+        let last_chunk_row = (row + 1).min(self.height - 1) / CHUNK_SIZE;
+        let first_chunk_column = column.saturating_sub(1) / CHUNK_SIZE;
+        let last_chunk_column = (column + 1).min(self.width - 1) / CHUNK_SIZE;
+
+        for chunk_row in first_chunk_row..=last_chunk_row {
+            for chunk_column in first_chunk_column..=last_chunk_column {
+                self.wake_chunk(chunk_row * self.chunks_wide + chunk_column);
+            }
+        }
+    }
+
+    // Grains beside a column decide where to go from its height, so when it
+    // changes the whole strip of chunks on either side has to look again
+    fn wake_columns_beside(&mut self, column: usize) {
+        let first_chunk_column = column.saturating_sub(1) / CHUNK_SIZE;
+        let last_chunk_column = (column + 1).min(self.width - 1) / CHUNK_SIZE;
+
+        for chunk_row in 0..self.chunks_high {
+            for chunk_column in first_chunk_column..=last_chunk_column {
+                self.wake_chunk(chunk_row * self.chunks_wide + chunk_column);
+            }
+        }
+    }
+
+    fn wake_chunk(&mut self, chunk: usize) {
+        self.awake[chunk] = true;
+        self.awake_next[chunk] = true;
+    }
+
+    pub fn awake_chunk_count(&self) -> usize {
+        self.awake_next.iter().filter(|awake| **awake).count()
+    }
+
+    // Called whenever a cell flips between empty and filled, keeps column_heights in sync
+    fn update_column_height(&mut self, row: usize, column: usize) {
+        let mut count = self.column_heights[column];
+
+        if self.is_empty(row, column) {
+            if row >= self.height - count {
+                count = self.height - 1 - row;
+            }
+        } else if row + 1 + count == self.height {
+            // The grain landed on top of the stack, which may now join up with grains above it
+            while count < self.height && !self.is_empty(self.height - 1 - count, column) {
+                count += 1;
+            }
+        }
+
+        if self.column_heights[column] != count {
+            self.column_heights[column] = count;
+            self.wake_columns_beside(column);
+        }
+    }
+
+    fn is_empty(&self, row: usize, column: usize) -> bool {
+        self.cell(row, column) == EMPTY
+    }
+
+    pub fn advance_frame(&mut self) {
+        std::mem::swap(&mut self.awake, &mut self.awake_next);
+        self.awake_next.fill(false);
+
+        let recording = self.history.pause_recording();
+        match self.schedule {
+            Schedule::RowScan => self.advance_frame_row_scan(),
+            Schedule::Checkerboard { threads } => self.advance_frame_checkerboard(threads)
+        }
+        self.history.resume_recording(recording);
+    }
+
+    fn advance_frame_row_scan(&mut self) {
+        // Grains on the bottom row never move
+        for row in (0..self.height - 1).rev() {
+            let mut direction_moved = Direction::Neither;
+            for chunk_column in 0..self.chunks_wide {
+                // Every cell of a sleeping or empty chunk would stay put, so skip straight past it
+                let chunk = (row / CHUNK_SIZE) * self.chunks_wide + chunk_column;
+                if !self.awake[chunk] || self.chunk_grain_counts[chunk] == 0 {
+                    direction_moved = Direction::Neither;
                     continue;
                 }
 
-                let fell = self.fall_down(row, column);
+                let first_column = chunk_column * CHUNK_SIZE;
+                for column in first_column..(first_column + CHUNK_SIZE).min(self.width) {
+                    if self.is_empty(row, column) {
+                        direction_moved = Direction::Neither;
+                        continue;
+                    }
+
+                    if direction_moved == Direction::Right {
+                        // This is because if a grain moves to the right we will try to reprocess it
+                        // This prevents a grain from being processed twice in 1 frame
+                        direction_moved = Direction::Neither;
+                        continue;
+                    }
+
+                    let fell = self.fall_down(row, column);
 
-                if !fell {
-                    direction_moved = self.move_horizontally(row, column);
+                    if !fell {
+                        direction_moved = self.move_horizontally(row, column);
+                    }
                 }
             }
         }
     }
 
     fn fall_down(&mut self, row: usize, column: usize) -> bool {
-        if self.grains[row + 1][column] != '-' {
+        if !self.is_empty(row + 1, column) {
             return false;
         }
 
-        self.grains[row + 1][column] = self.grains[row][column];
-        self.grains[row][column] = '-';
-        return true;
+        self.set_cell(row + 1, column, self.cell(row, column));
+        self.set_cell(row, column, EMPTY);
+        true
     }
 
     fn move_horizontally(&mut self, row: usize, column: usize) -> Direction {
         let direction: Direction = self.figure_out_direction_to_move(row, column);
 
         match direction {
-            Direction::LEFT => {
-                self.grains[row][column - 1] = self.grains[row][column];
-                self.grains[row][column] = '-';
+            Direction::Left => {
+                self.set_cell(row, column - 1, self.cell(row, column));
+                self.set_cell(row, column, EMPTY);
             },
-            Direction::RIGHT => {
-                self.grains[row][column + 1] = self.grains[row][column];
-                self.grains[row][column] = '-';
+            Direction::Right => {
+                self.set_cell(row, column + 1, self.cell(row, column));
+                self.set_cell(row, column, EMPTY);
             },
-            Direction::NEITHER => ()
+            Direction::Neither => ()
         };
 
         direction
     }
 
     fn figure_out_direction_to_move(&self, row: usize, column: usize) -> Direction {
-        let can_move_left = column != 0 && self.grains[row][column - 1] == '-';
-        let can_move_right = column + 1 < self.grains[row].len() && self.grains[row][column + 1] == '-';
-
-        if !can_move_left && !can_move_right {
-            return Direction::NEITHER;
-        }
-
-        let left_drop: usize = match can_move_left {
-            true => (self.grains.len() - row) - self.get_count_of_grains_in_column(column - 1),
-            false => 0
-        };
-
-        let right_drop: usize = match can_move_right {
-            true => (self.grains.len() - row) - self.get_count_of_grains_in_column(column + 1),
-            false => 0
-        };
-
-        // Prefer left movement
-        if left_drop != 0 && left_drop >= right_drop && left_drop - 1 > self.step {
-            return Direction::LEFT;
-        }
-
-        if right_drop != 0 && right_drop > left_drop && right_drop - 1 > self.step {
-            return Direction::RIGHT;
-        }
+        let can_move_left = column != 0 && self.is_empty(row, column - 1);
+        let can_move_right = column + 1 < self.width && self.is_empty(row, column + 1);
 
-        return Direction::NEITHER;
+        choose_direction(
+            self.height - row,
+            self.step,
+            can_move_left.then(|| self.get_count_of_grains_in_column(column - 1)),
+            can_move_right.then(|| self.get_count_of_grains_in_column(column + 1))
+        )
     }
 
     fn get_count_of_grains_in_column(&self, column: usize) -> usize {
-        let mut count = 0;
-        for i in (0..self.grains.len()).rev() {
-            if self.grains[i][column] != '-' {
-                count = count + 1;
-            } else {
-                break;
+        self.column_heights[column]
+    }
+
+    pub fn clear(&mut self) {
+        for row in 0..self.height {
+            for column in 0..self.width {
+                self.set_cell(row, column, EMPTY);
             }
         }
-        return count;
     }
 
     pub fn drop_sand_in_column(&mut self, column: usize, grain: char) {
@@ -142,54 +441,147 @@ impl Tank {
     }
 
     pub fn drop_sand(&mut self, row: usize, column: usize, grain: char, area: usize) {
-        assert!(column < self.grains[0].len(), "Cannot drop sand past a column larger than the width of the tank!");
+        self.try_drop_sand(row, column, grain, area).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_drop_sand_in_column(&mut self, column: usize, grain: char) -> Result<(), TankError> {
+        self.try_drop_sand(0, column, grain, 1)
+    }
 
+    // Fills the empty cells in an area x area square with its top left corner
+    // at (row, column). The square can hang off the tank but its corner can't
+    pub fn try_drop_sand(&mut self, row: usize, column: usize, grain: char, area: usize) -> Result<(), TankError> {
+        if row >= self.height || column >= self.width {
+            return Err(TankError::OutOfBounds { row, column, width: self.width, height: self.height });
+        }
+
+        let grain = try_to_cell(grain)?;
         for i in 0..area {
-            if row + i >= self.grains.len() {
+            if row + i >= self.height {
                 break;
             }
 
             for j in 0..area {
-                if column + j >= self.grains[i].len() {
+                if column + j >= self.width {
                     break;
                 }
-                    
-                if self.grains[row + i][column + j] == '-' {
-                    self.grains[row + i][column + j] = grain;
+
+                if self.is_empty(row + i, column + j) {
+                    self.set_cell(row + i, column + j, grain);
                 }
             }
         }
+
+        Ok(())
     }
 
-    pub fn to_string(&self) -> String {
-        let mut s = String::from("");
-        for v in self.grains.iter() {
-            for c in v.iter() {
-                s.push(*c);
-            }
-            s.push('\n');
+}
+
+// A clone starts with an empty undo history. Copying up to a hundred edits
+// along with every snapshot or clipboard would cost far more than the grains.
+// It starts its own worker threads if it needs them
+impl Clone for Tank {
+    fn clone(&self) -> Tank {
+        Tank {
+            grains: self.grains.clone(),
+            width: self.width,
+            height: self.height,
+            step: self.step,
+            schedule: self.schedule,
+            column_heights: self.column_heights.clone(),
+            chunks_wide: self.chunks_wide,
+            chunks_high: self.chunks_high,
+            chunk_grain_counts: self.chunk_grain_counts.clone(),
+            awake: self.awake.clone(),
+            awake_next: self.awake_next.clone(),
+            moved: self.moved.clone(),
+            workers: None,
+            history: history::History::default()
         }
-        s
     }
+}
+
+// Two tanks are equal when they hold the same grains, whatever their step,
+// schedule or history. Hashing follows the same rule so repeated states can be
+// spotted with a HashSet
+impl PartialEq for Tank {
+    fn eq(&self, other: &Tank) -> bool {
+        self.width == other.width && self.height == other.height && self.grains == other.grains
+    }
+}
 
-    pub fn equals(&self, other: &Tank) -> bool {
-        if self.grains.len() != other.grains.len() {
-            return false;
-        }
+impl Eq for Tank {}
 
-        if self.grains[0].len() != other.grains[0].len() {
-            return false;
-        }
+impl Hash for Tank {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.width.hash(state);
+        self.height.hash(state);
+        self.grains.hash(state);
+    }
+}
 
-        for i in 0..self.grains.len() {
-            for j in 0..self.grains[i].len() {
-                if self.grains[i][j] != other.grains[i][j] {
-                    return false;
-                }
-            }
+// The grid, one line per row, which is also what FromStr reads back
+impl fmt::Display for Tank {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.grains.chunks(self.width) {
+            writeln!(f, "{}", String::from_utf8_lossy(row))?;
         }
+        Ok(())
+    }
+}
+
+// Shows the grid row by row rather than one long list of bytes, so failed
+// assert_eq!s are readable
+impl fmt::Debug for Tank {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows: Vec<_> = self.grains.chunks(self.width).map(String::from_utf8_lossy).collect();
+        f.debug_struct("Tank")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("step", &self.step)
+            .field("grains", &rows)
+            .finish()
+    }
+}
+
+impl FromStr for Tank {
+    type Err = TankError;
 
-        return true;
+    fn from_str(text: &str) -> Result<Tank, TankError> {
+        Tank::try_from_grains(text.lines().map(|line| line.trim_end_matches('\r')))
+    }
+}
+
+// Works out which way a grain that cannot fall should slide. `depth` is the
+// grain's distance from the floor, and each side is the height of the column
+// next to it, or None if that side is blocked
+fn choose_direction(depth: usize, step: usize, left_column: Option<usize>, right_column: Option<usize>) -> Direction {
+    if left_column.is_none() && right_column.is_none() {
+        return Direction::Neither;
+    }
+
+    // Heights can be out of date under the checkerboard schedule, so treat a
+    // column that looks taller than the grain as no drop at all
+    let left_drop = left_column.map_or(0, |count| depth.saturating_sub(count));
+    let right_drop = right_column.map_or(0, |count| depth.saturating_sub(count));
+
+    // Prefer left movement
+    if left_drop != 0 && left_drop >= right_drop && left_drop - 1 > step {
+        return Direction::Left;
+    }
+
+    if right_drop != 0 && right_drop > left_drop && right_drop - 1 > step {
+        return Direction::Right;
+    }
+
+    Direction::Neither
+}
+
+fn try_to_cell(grain: char) -> Result<Cell, TankError> {
+    if grain.is_ascii_graphic() {
+        Ok(grain as Cell)
+    } else {
+        Err(TankError::UnknownSymbol { grain })
     }
 }
 
@@ -203,6 +595,7 @@ impl Tank {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::Random;
 
     #[test]
     fn test_one_column_init() {
@@ -212,7 +605,7 @@ mod tests {
             "-".to_string(),
             "-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -224,7 +617,7 @@ mod tests {
             "-".to_string(),
             "-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -237,7 +630,7 @@ mod tests {
             "g".to_string(),
             "-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -251,7 +644,7 @@ mod tests {
             "-".to_string(),
             "g".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -266,7 +659,7 @@ mod tests {
             "-".to_string(),
             "g".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -280,7 +673,7 @@ mod tests {
             "g".to_string(),
             "-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -295,7 +688,7 @@ mod tests {
             "g".to_string(),
             "g".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -311,7 +704,7 @@ mod tests {
             "g".to_string(),
             "g".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -325,7 +718,7 @@ mod tests {
             "-g".to_string(),
             "g-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -340,7 +733,7 @@ mod tests {
             "--".to_string(),
             "gg".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -357,7 +750,7 @@ mod tests {
             "gg".to_string(),
             "g-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -375,7 +768,7 @@ mod tests {
             "g-".to_string(),
             "gg".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -388,7 +781,7 @@ mod tests {
             "g-".to_string(),
             "g-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -402,7 +795,7 @@ mod tests {
             "g-".to_string(),
             "g-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -417,7 +810,7 @@ mod tests {
             "-g-".to_string(),
             "-g-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -433,7 +826,7 @@ mod tests {
             "g--".to_string(),
             "-g-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -450,7 +843,7 @@ mod tests {
             "---".to_string(),
             "gg-".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -471,7 +864,7 @@ mod tests {
             "---".to_string(),
             "ggg".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -497,7 +890,7 @@ mod tests {
             "gg-".to_string(),
             "ggg".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -522,7 +915,7 @@ mod tests {
             "ggg".to_string(),
             "ggg".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -548,7 +941,7 @@ mod tests {
             "-gg".to_string(),
             "ggg".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -603,7 +996,7 @@ mod tests {
             "gggg-".to_string(),
             "ggggg".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -660,7 +1053,7 @@ mod tests {
             "ggggg".to_string(),
             "ggggg".to_string()
         ));
-        assert!(t.equals(&expected), "expected \n{} \n actual\n{}", expected.to_string(), t.to_string());
+        assert_eq!(t, expected);
     }
 
     #[test]
@@ -677,5 +1070,242 @@ mod tests {
         assert!(t.get_count_of_grains_in_column(8) == 3, "get_count_of_grains_in_column is incorrect, expected: {} actual: {}", 3, t.get_count_of_grains_in_column(8));
         assert!(t.get_count_of_grains_in_column(11) == 0, "get_count_of_grains_in_column is incorrect, expected: {} actual: {}", 0, t.get_count_of_grains_in_column(11));
     }
+
+    #[test]
+    #[should_panic(expected = "row 0 column 2 is outside the 2x2 tank")]
+    fn test_grain_at_checks_the_column() {
+        let t: Tank = "-g\nr-\n".parse().unwrap();
+        t.grain_at(0, 2);
+    }
+
+    #[test]
+    fn test_from_grains_pads_short_rows() {
+        let t = Tank::from_grains(vec!(
+            "g--".to_string(),
+            "g".to_string()
+        ));
+
+        assert!(t.width() == 3 && t.height() == 2, "expected 3x2 actual {}x{}", t.width(), t.height());
+        assert!(t.to_string() == "g--\ng--\n", "unexpected grains\n{}", t);
+    }
+
+    #[test]
+    fn test_fallible_constructors() {
+        assert!(matches!(Tank::try_new(0, 3, 0), Err(TankError::ZeroSize { width: 0, height: 3 })));
+        assert!(matches!(Tank::try_new(1 << 40, 1 << 40, 0), Err(TankError::TooLarge { .. })));
+        assert!(matches!(Tank::try_new(usize::MAX, 2, 0), Err(TankError::TooLarge { .. })));
+        assert!(matches!(Tank::try_new(1, MAX_TANK_SIDE + 1, 0), Err(TankError::TooLarge { .. })));
+        assert!(Tank::try_new(MAX_TANK_SIDE, 1, 0).is_ok());
+        assert!(matches!(Tank::try_from_grains(Vec::<String>::new()), Err(TankError::ZeroSize { .. })));
+        assert!(matches!(Tank::try_from_grains(["g--", "g"]), Err(TankError::RaggedRow { row: 1, expected: 3, found: 1 })));
+        assert!(matches!(Tank::try_from_grains(["g-", "g\t"]), Err(TankError::UnknownSymbol { grain: '\t' })));
+
+        let t = Tank::try_from_grains(["-g-", "ggg"]).unwrap();
+        assert!(t.to_string() == "-g-\nggg\n", "unexpected grains\n{}", t);
+    }
+
+    #[test]
+    fn test_fallible_drops() {
+        let mut t = Tank::new(3, 2, 0);
+        assert!(t.try_drop_sand_in_column(3, 'g') == Err(TankError::OutOfBounds { row: 0, column: 3, width: 3, height: 2 }));
+        assert!(matches!(t.try_drop_sand(2, 0, 'g', 1), Err(TankError::OutOfBounds { .. })));
+        assert!(t.try_drop_sand_in_column(1, '\u{e9}') == Err(TankError::UnknownSymbol { grain: '\u{e9}' }));
+        assert!(t.to_string() == "---\n---\n", "failed drops shouldn't change the tank\n{}", t);
+
+        // The square can hang off the edge as long as its corner is inside
+        t.try_drop_sand(1, 2, 'g', 3).unwrap();
+        assert!(t.to_string() == "---\n--g\n", "unexpected grains\n{}", t);
+    }
+
+    #[test]
+    fn test_parse_reads_back_display() {
+        let t: Tank = "-g-\nggg\n".parse().unwrap();
+        assert_eq!(t.to_string(), "-g-\nggg\n");
+        assert_eq!(t.to_string().parse::<Tank>(), Ok(t));
+        assert_eq!("--\r\ng-\r\n".parse::<Tank>().map(|t| t.to_string()), Ok("--\ng-\n".to_string()));
+        assert_eq!("g--\ng\n".parse::<Tank>(), Err(TankError::RaggedRow { row: 1, expected: 3, found: 1 }));
+        assert_eq!("".parse::<Tank>(), Err(TankError::ZeroSize { width: 0, height: 0 }));
+    }
+
+    #[test]
+    fn test_equality_ignores_step_and_history() {
+        let t: Tank = "-g-\nggg\n".parse().unwrap();
+        let mut copy = t.clone();
+        copy.set_step(3);
+        assert_eq!(copy, t);
+
+        copy.begin_edit();
+        copy.drop_sand(0, 0, 'r', 1);
+        copy.end_edit();
+        assert_ne!(copy, t);
+        copy.undo();
+        assert_eq!(copy, t);
+        assert!(copy.can_redo() && !copy.clone().can_redo(), "clones shouldn't share the undo history");
+        assert!(format!("{:?}", t) == r#"Tank { width: 3, height: 2, step: 0, grains: ["-g-", "ggg"] }"#, "unexpected {:?}", t);
+    }
+
+    #[test]
+    fn test_hash_set_finds_a_settled_tank() {
+        let mut t: Tank = "-g-\n---\n---\n".parse().unwrap();
+        let mut seen = std::collections::HashSet::new();
+        let mut frames = 0;
+        while seen.insert(t.clone()) {
+            t.advance_frame();
+            frames += 1;
+        }
+        assert!(frames == 3, "expected the grain to settle after 3 frames, took {}", frames);
+        assert_eq!(t.to_string(), "---\n---\n-g-\n");
+    }
+
+    #[test]
+    fn test_column_heights_follow_edits() {
+        let mut t = Tank::from_grains(vec!(
+            "g-".to_string(),
+            "--".to_string(),
+            "g-".to_string()
+        ));
+        assert!(t.get_count_of_grains_in_column(0) == 1, "expected 1 actual {}", t.get_count_of_grains_in_column(0));
+
+        t.drop_sand(1, 0, 'g', 1);
+        assert!(t.get_count_of_grains_in_column(0) == 3, "expected 3 actual {}", t.get_count_of_grains_in_column(0));
+
+        t.set_cell(1, 0, EMPTY);
+        assert!(t.get_count_of_grains_in_column(0) == 1, "expected 1 actual {}", t.get_count_of_grains_in_column(0));
+        assert!(t.get_count_of_grains_in_column(1) == 0, "expected 0 actual {}", t.get_count_of_grains_in_column(1));
+    }
+
+    #[test]
+    fn test_column_heights_match_a_full_scan_after_settling() {
+        let mut t = Tank::new(7, 9, 0);
+        for i in 0..40 {
+            t.drop_sand_in_column((i * 3) % 7, 'g');
+            t.advance_frame();
+        }
+
+        for column in 0..t.width() {
+            let mut count = 0;
+            while count < t.height() && t.grain_at(t.height() - 1 - count, column) != '-' {
+                count += 1;
+            }
+            assert!(t.get_count_of_grains_in_column(column) == count, "column {} expected {} actual {}\n{}", column, count, t.get_count_of_grains_in_column(column), t);
+        }
+    }
+
+    #[test]
+    fn test_sleeping_chunks_match_processing_every_chunk() {
+        for step in 0..3 {
+            let mut t = Tank::new(100, 80, step);
+            let mut reference = Tank::new(100, 80, step);
+            let mut random = Random::new(7);
+
+            for frame in 0..400 {
+                if frame < 250 {
+                    let column = (random.next_number() >> 33) as usize % 100;
+                    t.drop_sand(0, column, 'g', 3);
+                    reference.drop_sand(0, column, 'g', 3);
+                }
+
+                reference.awake_next.fill(true);
+                t.advance_frame();
+                reference.advance_frame();
+                assert_eq!(t, reference, "step {} frame {}", step, frame);
+            }
+        }
+    }
+
+    #[test]
+    fn test_settled_tank_goes_to_sleep() {
+        let mut t = Tank::new(70, 70, 0);
+        t.drop_sand(0, 30, 'g', 10);
+        for _ in 0..300 {
+            t.advance_frame();
+        }
+        assert!(t.awake_chunk_count() == 0, "expected every chunk asleep, {} awake", t.awake_chunk_count());
+
+        t.drop_sand_in_column(65, 'g');
+        assert!(t.awake_chunk_count() > 0, "dropping sand should wake its chunk");
+    }
+
+    #[test]
+    fn test_lowering_a_column_wakes_grains_far_above_it() {
+        // A grain perched on a pillar in another chunk, waiting for the column beside it to get deeper
+        let mut t = Tank::new(34, 100, 40);
+        for row in 55..100 {
+            for column in 0..32 {
+                t.set_cell(row, column, b'g');
+            }
+        }
+        for row in 14..100 {
+            t.set_cell(row, 32, b'g');
+        }
+        for row in 0..100 {
+            t.set_cell(row, 33, b'g');
+        }
+        for _ in 0..3 {
+            t.advance_frame();
+        }
+        assert!(t.grain_at(14, 32) == 'g', "grain should still be waiting on its pillar");
+
+        t.set_cell(99, 31, EMPTY);
+        t.advance_frame();
+        t.advance_frame();
+        assert!(t.grain_at(14, 32) == '-', "grain should have moved off its pillar\n{}", t);
+    }
+
+    #[test]
+    fn test_resize_grow_from_bottom() {
+        let mut t = Tank::from_grains(vec!(
+            "g-".to_string(),
+            "gg".to_string()
+        ));
+        t.resize(4, 3, Anchor::Bottom);
+        let expected = Tank::from_grains(vec!(
+            "----".to_string(),
+            "-g--".to_string(),
+            "-gg-".to_string()
+        ));
+        assert_eq!(t, expected);
+    }
+
+    #[test]
+    fn test_resize_shrink_from_center() {
+        let mut t = Tank::from_grains(vec!(
+            "abc".to_string(),
+            "def".to_string(),
+            "ghi".to_string()
+        ));
+        t.resize(1, 1, Anchor::Center);
+        assert!(t.to_string() == "e\n", "expected e actual\n{}", t);
+    }
+
+    #[test]
+    fn test_resize_to_nothing_is_an_error() {
+        let mut t: Tank = "-g-\nggg\n".parse().unwrap();
+        assert!(t.try_resize(0, 3, Anchor::Bottom) == Err(TankError::ZeroSize { width: 0, height: 3 }));
+        assert!(t.try_resize(3, 0, Anchor::Bottom) == Err(TankError::ZeroSize { width: 3, height: 0 }));
+        assert!(t.to_string() == "-g-\nggg\n", "a failed resize shouldn't change the tank\n{}", t);
+
+        assert!(t.try_resize(2, 2, Anchor::BottomLeft) == Ok(()));
+        assert!(t.to_string() == "-g\ngg\n", "unexpected grains\n{}", t);
+    }
+
+    #[test]
+    fn test_resize_lets_grains_resettle() {
+        let mut t = Tank::from_grains(vec!(
+            "-g-".to_string(),
+            "ggg".to_string()
+        ));
+        t.resize(3, 4, Anchor::TopLeft);
+        t.advance_frame();
+        t.advance_frame();
+        t.advance_frame();
+        let expected = Tank::from_grains(vec!(
+            "---".to_string(),
+            "---".to_string(),
+            "-g-".to_string(),
+            "ggg".to_string()
+        ));
+        assert_eq!(t, expected);
+    }
 }
 
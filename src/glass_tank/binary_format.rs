@@ -0,0 +1,293 @@
+use super::{Tank, EMPTY, MAX_TANK_SIDE};
+use std::fmt;
+
+// A compact way to store a tank. All numbers are little endian.
+//
+//     magic    4 bytes  "FSND"
+//     version  u16
+//     then any number of sections, each one
+//         tag     4 bytes
+//         length  u32, the number of bytes that follow in this section
+//         payload
+//
+// Sections this version writes:
+//
+//     SIZE  width u32, height u32
+//     STEP  step u32
+//     GRNS  the symbol of every grain type in the tank, one byte each
+//     CELL  runs of identical cells from the top left, row by row. Each run is
+//           its length as a LEB128 number then one byte, 0 for empty or 1 + the
+//           grain's position in GRNS
+//
+// Sections a reader doesn't know are skipped, so later versions can add their
+// own without breaking older readers. The version only goes up when existing
+// sections change meaning
+pub const BINARY_VERSION: u16 = 1;
+pub const BINARY_MAGIC: &[u8; 4] = b"FSND";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinaryError {
+    NotATank,
+    UnsupportedVersion(u16),
+    // The data ends in the middle of something
+    Truncated,
+    MissingSection(&'static str),
+    BadSection { section: &'static str, message: String },
+}
+
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BinaryError::NotATank => write!(f, "not a saved tank, it doesn't start with {:?}", std::str::from_utf8(BINARY_MAGIC).unwrap_or_default()),
+            BinaryError::UnsupportedVersion(version) => write!(f, "version {} is newer than this program understands ({})", version, BINARY_VERSION),
+            BinaryError::Truncated => write!(f, "the file ends too early, it may be corrupt"),
+            BinaryError::MissingSection(section) => write!(f, "missing the {} section", section),
+            BinaryError::BadSection { section, message } => write!(f, "bad {} section: {}", section, message),
+        }
+    }
+}
+
+impl std::error::Error for BinaryError {}
+
+// Reads numbers off the front of a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], BinaryError> {
+        if count > self.bytes.len() {
+            return Err(BinaryError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, BinaryError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BinaryError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, BinaryError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn leb128(&mut self) -> Result<u64, BinaryError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(BinaryError::BadSection { section: "CELL", message: "run length is too long".to_string() })
+    }
+}
+
+fn push_section(bytes: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+    bytes.extend_from_slice(tag);
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(payload);
+}
+
+fn push_leb128(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+impl Tank {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut symbols: Vec<u8> = self.grains.iter().copied().filter(|cell| *cell != EMPTY).collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        // Every cell byte maps to its code in the CELL section
+        let mut codes = [0u8; 256];
+        for (i, symbol) in symbols.iter().enumerate() {
+            codes[*symbol as usize] = i as u8 + 1;
+        }
+
+        let mut cells = vec![];
+        for run in self.grains.chunk_by(|a, b| a == b) {
+            push_leb128(&mut cells, run.len() as u64);
+            cells.push(codes[run[0] as usize]);
+        }
+
+        let mut size = (self.width as u32).to_le_bytes().to_vec();
+        size.extend_from_slice(&(self.height as u32).to_le_bytes());
+
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+        push_section(&mut bytes, b"SIZE", &size);
+        push_section(&mut bytes, b"STEP", &(self.step as u32).to_le_bytes());
+        push_section(&mut bytes, b"GRNS", &symbols);
+        push_section(&mut bytes, b"CELL", &cells);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Tank, BinaryError> {
+        let mut reader = Reader { bytes };
+        if reader.take(4).ok() != Some(BINARY_MAGIC.as_slice()) {
+            return Err(BinaryError::NotATank);
+        }
+        let version = reader.u16()?;
+        if version > BINARY_VERSION {
+            return Err(BinaryError::UnsupportedVersion(version));
+        }
+
+        let mut size = None;
+        let mut step = 0;
+        let mut symbols = None;
+        let mut cells = None;
+        while !reader.bytes.is_empty() {
+            let tag = reader.take(4)?;
+            let length = reader.u32()? as usize;
+            let mut payload = Reader { bytes: reader.take(length)? };
+
+            match tag {
+                b"SIZE" => {
+                    let width = payload.u32()? as usize;
+                    let height = payload.u32()? as usize;
+                    if width == 0 || height == 0 || width > MAX_TANK_SIDE || height > MAX_TANK_SIDE {
+                        let message = format!("{}x{} is not a valid size, each side has to be 1 to {}", width, height, MAX_TANK_SIDE);
+                        return Err(BinaryError::BadSection { section: "SIZE", message });
+                    }
+                    size = Some((width, height));
+                },
+                b"STEP" => step = payload.u32()? as usize,
+                b"GRNS" => {
+                    if let Some(symbol) = payload.bytes.iter().find(|symbol| !symbol.is_ascii_graphic() || **symbol == EMPTY) {
+                        return Err(BinaryError::BadSection { section: "GRNS", message: format!("{:?} can't be used as a grain", *symbol as char) });
+                    }
+                    symbols = Some(payload.bytes);
+                },
+                b"CELL" => cells = Some(payload),
+                _ => ()
+            }
+        }
+
+        let (width, height) = size.ok_or(BinaryError::MissingSection("SIZE"))?;
+        let symbols = symbols.ok_or(BinaryError::MissingSection("GRNS"))?;
+        let mut cells = cells.ok_or(BinaryError::MissingSection("CELL"))?;
+        let cell_error = |message: String| BinaryError::BadSection { section: "CELL", message };
+
+        // The runs are checked before the tank is made, so a bad file can't get it allocated
+        let size = width * height;
+        let mut total = 0;
+        let mut check = Reader { bytes: cells.bytes };
+        while !check.bytes.is_empty() {
+            let run = check.leb128()?;
+            let code = check.u8()? as usize;
+            if run == 0 || run > (size - total) as u64 {
+                return Err(cell_error(format!("a run of {} cells doesn't fit in the tank", run)));
+            }
+            if code > symbols.len() {
+                return Err(cell_error(format!("grain {} is not in the GRNS section", code)));
+            }
+            total += run as usize;
+        }
+        if total != size {
+            return Err(cell_error(format!("expected {} cells, found {}", size, total)));
+        }
+
+        let mut tank = Tank::new(width, height, step);
+        let mut index = 0;
+        while !cells.bytes.is_empty() {
+            let run = cells.leb128()? as usize;
+            let code = cells.u8()? as usize;
+            if code > 0 {
+                for i in index..index + run {
+                    tank.set_cell(i / width, i % width, symbols[code - 1]);
+                }
+            }
+            index += run;
+        }
+        Ok(tank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use crate::test_support::Random;
+
+    fn sample_tank() -> Tank {
+        let mut t = Tank::new(200, 100, 2);
+        let mut random = Random::new(7);
+        for _ in 0..1500 {
+            let number = random.next_number();
+            let grain = ['S', 'Y', 'r'][(number >> 60) as usize % 3];
+            t.drop_sand_in_column((number >> 33) as usize % 200, grain);
+            t.advance_frame();
+        }
+        t
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let t = sample_tank();
+        let bytes = t.to_bytes();
+        let loaded = Tank::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, t);
+        assert!(loaded.step() == 2);
+        assert!(bytes.len() * 5 < t.to_text().len(), "expected {} bytes to be much smaller than the text", bytes.len());
+    }
+
+    #[test]
+    fn test_unknown_sections_are_skipped() {
+        let t = Tank::from_grains(vec!("-g-".to_string(), "ggg".to_string()));
+        let mut bytes = t.to_bytes();
+        bytes.extend_from_slice(b"NEWS");
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(b"abc");
+
+        let loaded = Tank::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, t);
+    }
+
+    #[test]
+    fn test_corrupt_bytes_are_errors() {
+        let bytes = Tank::from_grains(vec!("-g-".to_string(), "ggg".to_string())).to_bytes();
+        assert!(matches!(Tank::from_bytes(b"-g-\nggg\n"), Err(BinaryError::NotATank)));
+        assert!(matches!(Tank::from_bytes(&bytes[..bytes.len() - 1]), Err(BinaryError::Truncated)));
+
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert!(matches!(Tank::from_bytes(&newer), Err(BinaryError::UnsupportedVersion(2))));
+
+        // The last byte is the grain of the last run
+        let mut bad_grain = bytes.clone();
+        *bad_grain.last_mut().unwrap() = 9;
+        assert!(matches!(Tank::from_bytes(&bad_grain), Err(BinaryError::BadSection { section: "CELL", .. })));
+
+        // Sizes are checked before anything is allocated for them
+        for (width, height) in [(u32::MAX, u32::MAX), (200000, 200000), (MAX_TANK_SIDE as u32 + 1, 1)] {
+            let mut huge = BINARY_MAGIC.to_vec();
+            huge.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+            huge.extend_from_slice(b"SIZE\x08\x00\x00\x00");
+            huge.extend_from_slice(&width.to_le_bytes());
+            huge.extend_from_slice(&height.to_le_bytes());
+            huge.extend_from_slice(b"GRNS\x00\x00\x00\x00CELL\x02\x00\x00\x00\x01\x00");
+            assert!(matches!(Tank::from_bytes(&huge), Err(BinaryError::BadSection { section: "SIZE", .. })), "{}x{} should be refused", width, height);
+        }
+
+        let mut too_long = bytes.clone();
+        let run = too_long.len() - 2;
+        too_long[run] = 4;
+        assert!(matches!(Tank::from_bytes(&too_long), Err(BinaryError::BadSection { section: "CELL", .. })));
+    }
+}
@@ -0,0 +1,312 @@
+use super::{try_to_cell, Cell, Tank, TankError, EMPTY};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushShape {
+    Square,
+    Circle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaintMode {
+    // Only empty cells get a grain, like drop_sand
+    Fill,
+    // Grains already there are turned into the new grain, empty cells are left alone
+    Replace,
+    // Cells are emptied, whatever grain is being painted
+    Erase,
+}
+
+impl PaintMode {
+    // What a cell becomes when painted over, or None if it stays as it is
+    pub fn apply(self, current: Cell, grain: Cell) -> Option<Cell> {
+        match self {
+            PaintMode::Fill if current == EMPTY => Some(grain),
+            PaintMode::Replace if current != EMPTY => Some(grain),
+            PaintMode::Erase => Some(EMPTY),
+            _ => None
+        }
+    }
+}
+
+// What gets painted around the cursor
+#[derive(Debug, Clone)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub mode: PaintMode,
+    // Width of the brush in cells
+    pub size: usize,
+    // Chance from 0 to 1 of each cell under the brush getting a grain
+    pub density: f32,
+    // How many cells away from the brush a grain can land
+    pub scatter: usize,
+    seed: u64,
+}
+
+impl Brush {
+    pub fn new(shape: BrushShape, size: usize) -> Brush {
+        Brush { shape, mode: PaintMode::Fill, size: size.max(1), density: 1.0, scatter: 0, seed: 0x9E3779B97F4A7C15 }
+    }
+
+    // Density and scatter pick cells with a small generator of their own, so
+    // the same seed always paints the same grains
+    pub fn with_seed(mut self, seed: u64) -> Brush {
+        self.seed = seed | 1;
+        self
+    }
+
+    // Offsets from the brush's center cell, as (row, column)
+    pub fn footprint(&self) -> Vec<(isize, isize)> {
+        let size = self.size.max(1) as isize;
+        let first = -(size - 1) / 2;
+        let center = (size - 1) as f32 / 2.0;
+        let radius_squared = (size as f32 / 2.0).powi(2);
+
+        let mut offsets = vec![];
+        for row in 0..size {
+            for column in 0..size {
+                let inside = match self.shape {
+                    BrushShape::Square => true,
+                    BrushShape::Circle => (row as f32 - center).powi(2) + (column as f32 - center).powi(2) <= radius_squared
+                };
+                if inside {
+                    offsets.push((first + row, first + column));
+                }
+            }
+        }
+        offsets
+    }
+
+    fn next_random(&mut self) -> u64 {
+        // xorshift64
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+
+    fn chance(&mut self) -> f32 {
+        (self.next_random() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn jitter(&mut self) -> isize {
+        if self.scatter == 0 {
+            return 0;
+        }
+        (self.next_random() % (2 * self.scatter as u64 + 1)) as isize - self.scatter as isize
+    }
+}
+
+impl Tank {
+    pub fn paint(&mut self, brush: &mut Brush, row: usize, column: usize, grain: char) {
+        self.paint_stroke(brush, (row, column), (row, column), grain);
+    }
+
+    // Paints the brush along the line between two cells, so a fast moving
+    // cursor leaves a solid stroke instead of a trail of dots
+    pub fn paint_stroke(&mut self, brush: &mut Brush, from: (usize, usize), to: (usize, usize), grain: char) {
+        self.try_paint_stroke(brush, from, to, grain).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_paint(&mut self, brush: &mut Brush, row: usize, column: usize, grain: char) -> Result<(), TankError> {
+        self.try_paint_stroke(brush, (row, column), (row, column), grain)
+    }
+
+    // Like paint_stroke, but a grain that isn't printable ASCII is an error
+    // instead of a panic. Cells off the tank are skipped either way
+    pub fn try_paint_stroke(&mut self, brush: &mut Brush, from: (usize, usize), to: (usize, usize), grain: char) -> Result<(), TankError> {
+        let grain = try_to_cell(grain)?;
+        let footprint = brush.footprint();
+
+        // Every cell the stroke covers is only considered once, however many
+        // times the brush passes over it, so density means the same for any stroke
+        let mut covered = vec![];
+        let mut seen = std::collections::HashSet::new();
+        for (row, column) in line_between(from, to) {
+            for (row_offset, column_offset) in footprint.iter() {
+                let cell = (row as isize + row_offset, column as isize + column_offset);
+                if seen.insert(cell) {
+                    covered.push(cell);
+                }
+            }
+        }
+
+        for (row, column) in covered {
+            if brush.density < 1.0 && brush.chance() >= brush.density {
+                continue;
+            }
+
+            let row = row + brush.jitter();
+            let column = column + brush.jitter();
+            if row < 0 || column < 0 || row as usize >= self.height || column as usize >= self.width {
+                continue;
+            }
+
+            if let Some(cell) = brush.mode.apply(self.cell(row as usize, column as usize), grain) {
+                self.set_cell(row as usize, column as usize, cell);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Bresenham's line, both ends included
+pub fn line_between(from: (usize, usize), to: (usize, usize)) -> Vec<(usize, usize)> {
+    let (mut row, mut column) = (from.0 as isize, from.1 as isize);
+    let (end_row, end_column) = (to.0 as isize, to.1 as isize);
+    let row_distance = -(end_row - row).abs();
+    let column_distance = (end_column - column).abs();
+    let row_step = if row < end_row { 1 } else { -1 };
+    let column_step = if column < end_column { 1 } else { -1 };
+    let mut error = column_distance + row_distance;
+
+    let mut cells = vec![];
+    loop {
+        cells.push((row as usize, column as usize));
+        if row == end_row && column == end_column {
+            return cells;
+        }
+
+        let doubled = 2 * error;
+        if doubled >= row_distance {
+            error += row_distance;
+            column += column_step;
+        }
+        if doubled <= column_distance {
+            error += column_distance;
+            row += row_step;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use crate::test_support::grain_count;
+
+    #[test]
+    fn test_square_brush_is_centered() {
+        let mut t = Tank::new(5, 5, 0);
+        t.paint(&mut Brush::new(BrushShape::Square, 3), 2, 2, 'g');
+        let expected = Tank::from_grains(vec!(
+            "-----".to_string(),
+            "-ggg-".to_string(),
+            "-ggg-".to_string(),
+            "-ggg-".to_string(),
+            "-----".to_string()
+        ));
+        assert_eq!(t, expected);
+    }
+
+    #[test]
+    fn test_circle_brush_cuts_the_corners() {
+        let mut t = Tank::new(7, 7, 0);
+        t.paint(&mut Brush::new(BrushShape::Circle, 5), 3, 3, 'g');
+        let expected = Tank::from_grains(vec!(
+            "-------".to_string(),
+            "--ggg--".to_string(),
+            "-ggggg-".to_string(),
+            "-ggggg-".to_string(),
+            "-ggggg-".to_string(),
+            "--ggg--".to_string(),
+            "-------".to_string()
+        ));
+        assert_eq!(t, expected);
+    }
+
+    #[test]
+    fn test_brush_is_clipped_at_the_walls() {
+        let mut t = Tank::new(3, 3, 0);
+        t.paint(&mut Brush::new(BrushShape::Square, 3), 0, 0, 'g');
+        assert!(t.to_string() == "gg-\ngg-\n---\n", "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_stroke_has_no_gaps() {
+        let mut t = Tank::new(12, 6, 0);
+        t.paint_stroke(&mut Brush::new(BrushShape::Square, 1), (0, 0), (5, 11), 'g');
+        assert!(grain_count(&t) == 12, "expected one grain per column\n{}", t);
+        for column in 0..12 {
+            assert!((0..6).any(|row| t.grain_at(row, column) == 'g'), "gap in column {}\n{}", column, t);
+        }
+    }
+
+    #[test]
+    fn test_density_thins_out_the_brush() {
+        let mut t = Tank::new(40, 40, 0);
+        let mut brush = Brush::new(BrushShape::Square, 40).with_seed(5);
+        brush.density = 0.25;
+        t.paint(&mut brush, 19, 19, 'g');
+        let count = grain_count(&t);
+        assert!(count > 300 && count < 500, "expected about 400 grains, got {}", count);
+    }
+
+    #[test]
+    fn test_scatter_moves_grains_off_the_brush() {
+        let mut t = Tank::new(21, 21, 0);
+        let mut brush = Brush::new(BrushShape::Square, 1).with_seed(9);
+        brush.scatter = 5;
+        for _ in 0..50 {
+            t.paint(&mut brush, 10, 10, 'g');
+        }
+        assert!(grain_count(&t) > 10, "scattered grains should spread out\n{}", t);
+        for (row, line) in t.to_string().lines().enumerate() {
+            for (column, grain) in line.chars().enumerate() {
+                if grain == 'g' {
+                    assert!(row.abs_diff(10) <= 5 && column.abs_diff(10) <= 5, "grain at {},{} is too far away", row, column);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_eraser_clears_under_the_brush() {
+        let mut t = Tank::from_grains(vec!(
+            "ggg".to_string(),
+            "ggg".to_string(),
+            "ggg".to_string()
+        ));
+        let mut eraser = Brush::new(BrushShape::Square, 2);
+        eraser.mode = PaintMode::Erase;
+        t.paint(&mut eraser, 1, 1, 'g');
+        assert!(t.to_string() == "ggg\ng--\ng--\n", "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_replace_recolors_grains_in_place() {
+        let mut t = Tank::from_grains(vec!(
+            "---".to_string(),
+            "-g-".to_string(),
+            "ggg".to_string()
+        ));
+        let mut brush = Brush::new(BrushShape::Square, 3);
+        brush.mode = PaintMode::Replace;
+        t.paint(&mut brush, 1, 1, 'r');
+        assert!(t.to_string() == "---\n-r-\nrrr\n", "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_erasing_lets_the_pile_above_fall() {
+        let mut t = Tank::from_grains(vec!(
+            "-r-".to_string(),
+            "-g-".to_string(),
+            "ggg".to_string()
+        ));
+        let mut eraser = Brush::new(BrushShape::Square, 1);
+        eraser.mode = PaintMode::Erase;
+        t.paint(&mut eraser, 1, 1, 'g');
+        t.advance_frame();
+        assert!(t.to_string() == "---\n-r-\nggg\n", "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_painting_a_bad_grain_is_an_error() {
+        let mut t = Tank::new(3, 3, 0);
+        let mut brush = Brush::new(BrushShape::Square, 3);
+        assert!(t.try_paint(&mut brush, 1, 1, '\u{e9}') == Err(TankError::UnknownSymbol { grain: '\u{e9}' }));
+        assert!(t.try_paint_stroke(&mut brush, (0, 0), (2, 2), '\n') == Err(TankError::UnknownSymbol { grain: '\n' }));
+        assert!(grain_count(&t) == 0);
+
+        assert!(t.try_paint(&mut brush, 1, 1, 'g') == Ok(()));
+        assert!(grain_count(&t) == 9);
+    }
+}
@@ -0,0 +1,173 @@
+use super::{try_to_cell, Cell, Tank, TankError, EMPTY};
+
+// Where a cell is in a tank. Rows count down from the top and columns across
+// from the left, so naming them saves mixing up the order of a (usize, usize)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Position {
+    pub row: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub const fn new(row: usize, column: usize) -> Position {
+        Position { row, column }
+    }
+
+    // The position `rows` down and `columns` right of this one, if it isn't above or left of the tank
+    pub fn offset(self, rows: isize, columns: isize) -> Option<Position> {
+        Some(Position {
+            row: self.row.checked_add_signed(rows)?,
+            column: self.column.checked_add_signed(columns)?,
+        })
+    }
+}
+
+// Tuples elsewhere in the tank are (row, column) too
+impl From<(usize, usize)> for Position {
+    fn from((row, column): (usize, usize)) -> Position {
+        Position { row, column }
+    }
+}
+
+impl From<Position> for (usize, usize) {
+    fn from(position: Position) -> (usize, usize) {
+        (position.row, position.column)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Neighborhood {
+    // The eight cells around, corners included
+    Moore,
+    // Only the four cells sharing a side
+    VonNeumann,
+}
+
+impl Neighborhood {
+    // As (row, column) offsets, clockwise from the top left or from the top
+    fn offsets(self) -> &'static [(isize, isize)] {
+        match self {
+            Neighborhood::Moore => &[(-1, -1), (-1, 0), (-1, 1), (0, 1), (1, 1), (1, 0), (1, -1), (0, -1)],
+            Neighborhood::VonNeumann => &[(-1, 0), (0, 1), (1, 0), (0, -1)]
+        }
+    }
+}
+
+impl Tank {
+    pub fn contains(&self, position: Position) -> bool {
+        position.row < self.height && position.column < self.width
+    }
+
+    // Grains are chars here like in drop_sand and grain_at, with '-' for an empty cell
+    pub fn get(&self, position: Position) -> Option<char> {
+        self.contains(position).then(|| self.grain_at(position.row, position.column))
+    }
+
+    // Puts any grain, '-' for empty included, at a position and returns what was there.
+    // Unlike drop_sand it overwrites grains. Goes in the undo history like any other edit
+    pub fn set(&mut self, position: Position, grain: char) -> Result<char, TankError> {
+        if !self.contains(position) {
+            return Err(TankError::OutOfBounds { row: position.row, column: position.column, width: self.width, height: self.height });
+        }
+
+        let cell = try_to_cell(grain)?;
+        let old = self.grain_at(position.row, position.column);
+        self.set_cell(position.row, position.column, cell);
+        Ok(old)
+    }
+
+    // Every cell row by row from the top left, empty ones included
+    pub fn cells(&self) -> impl Iterator<Item = (Position, char)> + '_ {
+        let width = self.width;
+        self.grains.iter().enumerate().map(move |(i, cell)| (Position::new(i / width, i % width), *cell as char))
+    }
+
+    // Every cell holding a grain, in the same order as cells
+    pub fn grains(&self) -> impl Iterator<Item = (Position, char)> + '_ {
+        self.cells().filter(|(_, grain)| *grain != EMPTY as char)
+    }
+
+    // Whole rows are slices of the stored cells, each the ASCII byte of its grain
+    pub fn row(&self, row: usize) -> Option<&[Cell]> {
+        (row < self.height).then(|| &self.grains[row * self.width..(row + 1) * self.width])
+    }
+
+    // The grains of a column from the top down
+    pub fn column(&self, column: usize) -> Option<impl DoubleEndedIterator<Item = char> + ExactSizeIterator + '_> {
+        (column < self.width).then(|| self.grains.iter().skip(column).step_by(self.width).map(|cell| *cell as char))
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[Cell]> + ExactSizeIterator + '_ {
+        self.grains.chunks(self.width)
+    }
+
+    // The cells next to a position that are inside the tank, so edges and
+    // corners have fewer. Empty if the position itself is outside the tank
+    pub fn neighbors(&self, position: Position, neighborhood: Neighborhood) -> impl Iterator<Item = (Position, char)> + '_ {
+        let inside = self.contains(position);
+        neighborhood.offsets().iter()
+            .filter(move |_| inside)
+            .filter_map(move |(rows, columns)| position.offset(*rows, *columns))
+            .filter_map(|neighbor| Some((neighbor, self.get(neighbor)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    fn sample() -> Tank {
+        "-g-\nrg-\nrgg\n".parse().unwrap()
+    }
+
+    #[test]
+    fn test_get_and_set_check_bounds() {
+        let mut t = sample();
+        assert!(t.get(Position::new(1, 0)) == Some('r'));
+        assert!(t.get(Position::new(3, 0)).is_none() && t.get(Position::new(0, 3)).is_none());
+
+        assert!(t.set(Position::new(0, 0), 'y') == Ok('-'));
+        assert!(t.set(Position::new(1, 1), '-') == Ok('g'));
+        assert!(t.set(Position::new(0, 3), 'y') == Err(TankError::OutOfBounds { row: 0, column: 3, width: 3, height: 3 }));
+        assert!(t.set(Position::new(0, 0), ' ') == Err(TankError::UnknownSymbol { grain: ' ' }));
+        assert!(t.set(Position::new(0, 0), '\u{e9}') == Err(TankError::UnknownSymbol { grain: '\u{e9}' }));
+        assert!(t.to_string() == "yg-\nr--\nrgg\n", "unexpected grains\n{}", t);
+
+        // The grain left hanging over the hole falls into it
+        t.advance_frame();
+        assert!(t.to_string() == "y--\nrg-\nrgg\n", "unexpected grains\n{}", t);
+    }
+
+    #[test]
+    fn test_iterating_cells_rows_and_columns() {
+        let t = sample();
+        assert!(t.cells().count() == 9);
+        assert!(t.cells().nth(4) == Some((Position::new(1, 1), 'g')));
+
+        let grains: Vec<(usize, usize)> = t.grains().map(|(position, _)| position.into()).collect();
+        assert!(grains == [(0, 1), (1, 0), (1, 1), (2, 0), (2, 1), (2, 2)], "unexpected {:?}", grains);
+
+        assert!(t.row(2) == Some(b"rgg".as_slice()) && t.row(3).is_none());
+        assert!(t.column(0).unwrap().collect::<String>() == "-rr");
+        assert!(t.column(2).unwrap().rev().collect::<String>() == "g--");
+        assert!(t.column(3).is_none());
+        assert!(t.rows().len() == 3 && t.rows().last() == Some(b"rgg".as_slice()));
+    }
+
+    #[test]
+    fn test_neighbors_stay_inside_the_tank() {
+        let t = sample();
+        let around = |position: Position, neighborhood: Neighborhood| -> String {
+            t.neighbors(position, neighborhood).map(|(_, grain)| grain).collect()
+        };
+
+        assert!(around(Position::new(1, 1), Neighborhood::Moore) == "-g--ggrr");
+        assert!(around(Position::new(1, 1), Neighborhood::VonNeumann) == "g-gr");
+        assert!(around(Position::new(0, 0), Neighborhood::Moore) == "ggr");
+        assert!(around(Position::new(2, 2), Neighborhood::VonNeumann) == "-g");
+        assert!(around(Position::new(5, 5), Neighborhood::Moore).is_empty());
+
+        let positions: Vec<Position> = t.neighbors(Position::new(0, 2), Neighborhood::VonNeumann).map(|(position, _)| position).collect();
+        assert!(positions == [Position::new(1, 2), Position::new(0, 1)]);
+    }
+}
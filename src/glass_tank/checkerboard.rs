@@ -0,0 +1,316 @@
+use super::{choose_direction, Cell, Direction, Tank, CHUNK_SIZE, EMPTY};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+// Each pass updates every chunk whose (column, row) parity matches. Chunks in
+// the same pass are a whole chunk apart, and a grain can only move one cell per
+// update, so no two chunks in a pass can read or write the same cell
+const PASSES: [(usize, usize); 4] = [(0, 1), (1, 1), (0, 0), (1, 0)];
+
+// A copy of one chunk plus the border cells its grains can move into. Workers
+// only ever see their own copy, and the results are merged back in a fixed
+// order so the outcome doesn't depend on how the threads were scheduled
+struct ChunkWork {
+    top: usize,
+    left: usize,
+    width: usize,
+    cells: Vec<Cell>,
+    moved: Vec<bool>,
+    // Tank rows and columns of the chunk itself, bottom row of the tank excluded
+    rows: std::ops::Range<usize>,
+    columns: std::ops::Range<usize>,
+}
+
+// One worker's share of a pass, and where it starts in the pass so the
+// results can be put back in order
+struct Job {
+    first: usize,
+    chunks: Vec<ChunkWork>,
+    heights: Arc<[usize]>,
+    tank_width: usize,
+    tank_height: usize,
+    step: usize,
+    done: mpsc::Sender<(usize, Vec<ChunkWork>)>,
+}
+
+// Threads kept on the tank between frames, so each pass only hands chunks out
+// instead of starting threads of its own. Dropping this closes the queue and
+// each thread ends once it finds it closed
+pub(super) struct Workers {
+    threads: usize,
+    jobs: mpsc::Sender<Job>,
+}
+
+impl Workers {
+    fn new(threads: usize) -> Workers {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+
+        for _ in 0..threads {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || loop {
+                // The queue is only locked while waiting for the next job
+                let job = queue.lock().unwrap().recv();
+                let Ok(mut job) = job else {
+                    return;
+                };
+
+                for chunk in job.chunks.iter_mut() {
+                    chunk.simulate(&job.heights, job.tank_width, job.tank_height, job.step);
+                }
+                // The pass only stops listening if it has given up on the frame
+                let _ = job.done.send((job.first, job.chunks));
+            });
+        }
+
+        Workers { threads, jobs }
+    }
+
+    // Simulates the chunks spread over the workers and gives them back in the order they came in
+    fn simulate(&self, work: Vec<ChunkWork>, heights: &[usize], tank_width: usize, tank_height: usize, step: usize) -> Vec<ChunkWork> {
+        let heights: Arc<[usize]> = heights.into();
+        let per_thread = work.len().div_ceil(self.threads);
+        let (done, results) = mpsc::channel();
+
+        let mut work = work.into_iter().peekable();
+        let (mut first, mut sent) = (0, 0);
+        while work.peek().is_some() {
+            let chunks: Vec<ChunkWork> = work.by_ref().take(per_thread).collect();
+            let count = chunks.len();
+            self.jobs.send(Job { first, chunks, heights: Arc::clone(&heights), tank_width, tank_height, step, done: done.clone() })
+                .expect("checkerboard workers stopped");
+            first += count;
+            sent += 1;
+        }
+
+        drop(done);
+        let mut finished: Vec<(usize, Vec<ChunkWork>)> = (0..sent)
+            .map(|_| results.recv().expect("checkerboard workers stopped"))
+            .collect();
+        finished.sort_unstable_by_key(|(first, _)| *first);
+        finished.into_iter().flat_map(|(_, chunks)| chunks).collect()
+    }
+}
+
+impl Tank {
+    pub(super) fn advance_frame_checkerboard(&mut self, threads: usize) {
+        // A grain that crossed into a chunk updated in a later pass mustn't move twice in one frame
+        self.moved.clear();
+        self.moved.resize(self.grains.len(), false);
+
+        if threads > 1 && self.workers.as_ref().is_none_or(|workers| workers.threads != threads) {
+            self.workers = Some(Workers::new(threads));
+        }
+
+        for (parity_column, parity_row) in PASSES {
+            let mut work = self.collect_chunk_work(parity_column, parity_row);
+            if work.is_empty() {
+                continue;
+            }
+
+            // Column heights are read as they were at the start of the pass
+            let heights = &self.column_heights;
+            let (tank_width, tank_height, step) = (self.width, self.height, self.step);
+
+            match &self.workers {
+                Some(workers) if threads > 1 && work.len() > 1 => {
+                    work = workers.simulate(work, heights, tank_width, tank_height, step);
+                },
+                _ => {
+                    for chunk in work.iter_mut() {
+                        chunk.simulate(heights, tank_width, tank_height, step);
+                    }
+                }
+            }
+
+            for chunk in work {
+                self.merge_chunk_work(chunk);
+            }
+        }
+    }
+
+    fn collect_chunk_work(&self, parity_column: usize, parity_row: usize) -> Vec<ChunkWork> {
+        let mut work = vec![];
+
+        for chunk_row in (parity_row..self.chunks_high).step_by(2) {
+            for chunk_column in (parity_column..self.chunks_wide).step_by(2) {
+                let chunk = chunk_row * self.chunks_wide + chunk_column;
+                if !self.awake[chunk] || self.chunk_grain_counts[chunk] == 0 {
+                    continue;
+                }
+
+                let rows = chunk_row * CHUNK_SIZE..((chunk_row + 1) * CHUNK_SIZE).min(self.height - 1);
+                let columns = chunk_column * CHUNK_SIZE..((chunk_column + 1) * CHUNK_SIZE).min(self.width);
+                if rows.is_empty() {
+                    continue;
+                }
+
+                let top = rows.start;
+                let bottom = rows.end + 1;
+                let left = columns.start.saturating_sub(1);
+                let right = (columns.end + 1).min(self.width);
+
+                let mut cells = Vec::with_capacity((bottom - top) * (right - left));
+                let mut moved = Vec::with_capacity(cells.capacity());
+                for row in top..bottom {
+                    let start = self.index(row, left);
+                    cells.extend_from_slice(&self.grains[start..start + right - left]);
+                    moved.extend_from_slice(&self.moved[start..start + right - left]);
+                }
+
+                work.push(ChunkWork { top, left, width: right - left, cells, moved, rows, columns });
+            }
+        }
+
+        work
+    }
+
+    fn merge_chunk_work(&mut self, chunk: ChunkWork) {
+        for (i, (cell, moved)) in chunk.cells.into_iter().zip(chunk.moved).enumerate() {
+            let row = chunk.top + i / chunk.width;
+            let column = chunk.left + i % chunk.width;
+            let index = self.index(row, column);
+            self.moved[index] = moved;
+            self.set_cell(row, column, cell);
+        }
+    }
+}
+
+impl ChunkWork {
+    fn index(&self, row: usize, column: usize) -> usize {
+        (row - self.top) * self.width + column - self.left
+    }
+
+    fn is_empty(&self, row: usize, column: usize) -> bool {
+        self.cells[self.index(row, column)] == EMPTY
+    }
+
+    fn move_grain(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let from = self.index(from.0, from.1);
+        let to = self.index(to.0, to.1);
+        self.cells[to] = self.cells[from];
+        self.cells[from] = EMPTY;
+        self.moved[to] = true;
+    }
+
+    // Same rules as the row scan, limited to this chunk
+    fn simulate(&mut self, heights: &[usize], tank_width: usize, tank_height: usize, step: usize) {
+        for row in self.rows.clone().rev() {
+            let mut direction_moved = Direction::Neither;
+            for column in self.columns.clone() {
+                if self.is_empty(row, column) || self.moved[self.index(row, column)] {
+                    direction_moved = Direction::Neither;
+                    continue;
+                }
+
+                if direction_moved == Direction::Right {
+                    direction_moved = Direction::Neither;
+                    continue;
+                }
+
+                if self.is_empty(row + 1, column) {
+                    self.move_grain((row, column), (row + 1, column));
+                    continue;
+                }
+
+                let can_move_left = column != 0 && self.is_empty(row, column - 1);
+                let can_move_right = column + 1 < tank_width && self.is_empty(row, column + 1);
+                direction_moved = choose_direction(
+                    tank_height - row,
+                    step,
+                    can_move_left.then(|| heights[column - 1]),
+                    can_move_right.then(|| heights[column + 1])
+                );
+
+                match direction_moved {
+                    Direction::Left => self.move_grain((row, column), (row, column - 1)),
+                    Direction::Right => self.move_grain((row, column), (row, column + 1)),
+                    Direction::Neither => ()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use crate::test_support::{grain_count, Random};
+
+    fn scatter_sand(t: &mut Tank, random: &mut Random) {
+        let number = random.next_number();
+        let column = (number >> 33) as usize % t.width();
+        let grain = ['R', 'G', 'B'][(number >> 20) as usize % 3];
+        t.drop_sand(0, column, grain, 4);
+    }
+
+    #[test]
+    fn test_thread_count_does_not_change_the_result() {
+        let mut serial = Tank::new(150, 110, 1);
+        serial.set_schedule(Schedule::Checkerboard { threads: 1 });
+        let mut random = Random::new(11);
+        let mut frames = vec![];
+        for frame in 0..300 {
+            if frame < 200 {
+                scatter_sand(&mut serial, &mut random);
+            }
+            serial.advance_frame();
+            frames.push(serial.to_string());
+        }
+
+        for threads in [2, 3, 8] {
+            let mut parallel = Tank::new(150, 110, 1);
+            parallel.set_schedule(Schedule::Checkerboard { threads });
+            let mut random = Random::new(11);
+            for (frame, expected) in frames.iter().enumerate() {
+                if frame < 200 {
+                    scatter_sand(&mut parallel, &mut random);
+                }
+                parallel.advance_frame();
+                assert!(parallel.to_string() == *expected, "{} threads diverged on frame {}", threads, frame);
+            }
+        }
+    }
+
+    #[test]
+    fn test_worker_threads_are_kept_between_frames() {
+        let mut t = Tank::new(100, 70, 0);
+        t.set_schedule(Schedule::Checkerboard { threads: 3 });
+        let mut random = Random::new(5);
+        for _ in 0..10 {
+            scatter_sand(&mut t, &mut random);
+            t.advance_frame();
+        }
+        assert!(t.workers.as_ref().map(|workers| workers.threads) == Some(3));
+
+        t.set_schedule(Schedule::Checkerboard { threads: 2 });
+        t.advance_frame();
+        assert!(t.workers.as_ref().map(|workers| workers.threads) == Some(2));
+    }
+
+    #[test]
+    fn test_grains_cross_chunks_once_per_frame() {
+        let mut t = Tank::new(CHUNK_SIZE * 2, CHUNK_SIZE * 3, 0);
+        t.set_schedule(Schedule::Checkerboard { threads: 2 });
+        t.drop_sand(CHUNK_SIZE - 1, 3, 'g', 1);
+        t.advance_frame();
+        assert!(t.grain_at(CHUNK_SIZE, 3) == 'g', "grain should fall exactly one cell\n{}", t);
+    }
+
+    #[test]
+    fn test_checkerboard_keeps_every_grain() {
+        let mut t = Tank::new(100, 70, 0);
+        t.set_schedule(Schedule::Checkerboard { threads: 4 });
+        let mut random = Random::new(3);
+        for _ in 0..150 {
+            scatter_sand(&mut t, &mut random);
+            t.advance_frame();
+        }
+        let count = grain_count(&t);
+        for _ in 0..200 {
+            t.advance_frame();
+        }
+        assert!(grain_count(&t) == count, "expected {} grains actual {}", count, grain_count(&t));
+        assert!(t.awake_chunk_count() == 0, "settled tank should be asleep, {} chunks awake", t.awake_chunk_count());
+    }
+}
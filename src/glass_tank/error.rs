@@ -0,0 +1,28 @@
+use super::MAX_TANK_SIDE;
+use std::fmt;
+
+// What can go wrong building or editing a tank from input that hasn't been checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TankError {
+    ZeroSize { width: usize, height: usize },
+    // A side is longer than MAX_TANK_SIDE
+    TooLarge { width: usize, height: usize },
+    RaggedRow { row: usize, expected: usize, found: usize },
+    // Grains have to be printable ASCII characters other than the empty cell
+    UnknownSymbol { grain: char },
+    OutOfBounds { row: usize, column: usize, width: usize, height: usize },
+}
+
+impl fmt::Display for TankError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TankError::ZeroSize { width, height } => write!(f, "a tank needs a width and height of at least 1, got {}x{}", width, height),
+            TankError::TooLarge { width, height } => write!(f, "a tank can be at most {}x{}, got {}x{}", MAX_TANK_SIDE, MAX_TANK_SIDE, width, height),
+            TankError::RaggedRow { row, expected, found } => write!(f, "row {} has {} cells but the first row has {}", row, found, expected),
+            TankError::UnknownSymbol { grain } => write!(f, "{:?} can't be used as a grain, grains are printable ASCII characters", grain),
+            TankError::OutOfBounds { row, column, width, height } => write!(f, "row {} column {} is outside the {}x{} tank", row, column, width, height),
+        }
+    }
+}
+
+impl std::error::Error for TankError {}
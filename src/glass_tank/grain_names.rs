@@ -0,0 +1,20 @@
+// What each grain symbol is called. Saved text tanks name their grains in the
+// legend, and the viewer's palette gives the same symbols their colors
+pub const GRAIN_NAMES: [(char, &str); 12] = [
+    ('P', "Pink"),
+    ('F', "Magenta"),
+    ('R', "Red"),
+    ('M', "Maroon"),
+    ('O', "Orange"),
+    ('S', "Beige"),
+    ('Y', "Yellow"),
+    ('G', "Green"),
+    ('C', "Sky Blue"),
+    ('B', "Blue"),
+    ('U', "Purple"),
+    ('W', "White"),
+];
+
+pub fn grain_name(symbol: char) -> Option<&'static str> {
+    GRAIN_NAMES.iter().find(|(named, _)| *named == symbol).map(|(_, name)| *name)
+}
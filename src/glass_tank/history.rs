@@ -0,0 +1,287 @@
+use super::{Cell, Tank};
+use std::collections::{HashMap, VecDeque};
+
+// Oldest edits are forgotten once there are more than this many to undo
+const HISTORY_LIMIT: usize = 100;
+
+// The cells one edit changed, as (index, before, after)
+#[derive(Debug, Clone, Default)]
+struct Delta {
+    cells: Vec<(u32, Cell, Cell)>,
+}
+
+// Undo and redo stacks for edits made to a tank.
+//
+// Only cells the edit itself writes between begin_edit and end_edit are
+// written down, never grains the simulation moves in the meantime, so an entry
+// is never bigger than its edit however busy the tank is. Grains keep falling
+// after an edit, so undoing only puts back the cells that still hold what the
+// edit left there, and never disturbs grains that moved in since
+#[derive(Debug, Clone, Default)]
+pub(super) struct History {
+    undo: VecDeque<Delta>,
+    redo: Vec<Delta>,
+    // Each cell the open edit wrote as (index, before, after), and where it is in
+    // the journal so writing it again only updates its after value
+    journal: Vec<(u32, Cell, Cell)>,
+    journaled: HashMap<u32, usize>,
+    // Off outside an edit and while advance_frame runs
+    recording: bool,
+    in_edit: bool,
+}
+
+impl History {
+    // Called with the old and new value of every cell that changes
+    pub(super) fn record(&mut self, index: usize, before: Cell, after: Cell) {
+        if !self.recording {
+            return;
+        }
+
+        let index = index as u32;
+        match self.journaled.get(&index) {
+            Some(slot) => self.journal[*slot].2 = after,
+            None => {
+                self.journaled.insert(index, self.journal.len());
+                self.journal.push((index, before, after));
+            }
+        }
+    }
+
+    // Turns the journal into a delta, leaving out cells the edit put back as they were
+    fn take_journal(&mut self) -> Delta {
+        self.journaled.clear();
+        let cells = self.journal.drain(..).filter(|(_, before, after)| before != after).collect();
+        Delta { cells }
+    }
+
+    // Keeps grains the simulation moves out of the open edit until resume_recording
+    pub(super) fn pause_recording(&mut self) -> bool {
+        std::mem::replace(&mut self.recording, false)
+    }
+
+    pub(super) fn resume_recording(&mut self, recording: bool) {
+        self.recording = recording;
+    }
+
+    // How many cells the undo and redo stacks hold between them
+    #[cfg(test)]
+    fn stored_cells(&self) -> usize {
+        self.undo.iter().chain(self.redo.iter()).map(|delta| delta.cells.len()).sum()
+    }
+}
+
+impl Tank {
+    // Every cell written between begin_edit and end_edit is undone together.
+    // Grains falling in the meantime aren't part of the edit. Calls don't nest
+    pub fn begin_edit(&mut self) {
+        if self.history.in_edit {
+            return;
+        }
+
+        self.history.redo.clear();
+        self.history.in_edit = true;
+        self.history.recording = true;
+    }
+
+    pub fn end_edit(&mut self) {
+        if !self.history.in_edit {
+            return;
+        }
+
+        self.history.in_edit = false;
+        self.history.recording = false;
+        let delta = self.history.take_journal();
+        if !delta.cells.is_empty() {
+            self.history.undo.push_back(delta);
+            if self.history.undo.len() > HISTORY_LIMIT {
+                self.history.undo.pop_front();
+            }
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.history.redo.is_empty()
+    }
+
+    // Reverts the last edit, so undoing straight away gives back the grid as it
+    // was before it. Only cells still holding what the edit left there are put
+    // back, so a grain that has fallen away since isn't put back twice. Returns
+    // false if there was nothing to undo
+    pub fn undo(&mut self) -> bool {
+        self.end_edit();
+        let Some(delta) = self.history.undo.pop_back() else {
+            return false;
+        };
+
+        for (index, before, after) in delta.cells.iter() {
+            if self.grains[*index as usize] == *after {
+                self.set_index(*index as usize, *before);
+            }
+        }
+        self.history.redo.push(delta);
+        true
+    }
+
+    // Makes the last undone edit again, in the cells that haven't changed since it was undone
+    pub fn redo(&mut self) -> bool {
+        self.end_edit();
+        let Some(delta) = self.history.redo.pop() else {
+            return false;
+        };
+
+        for (index, before, after) in delta.cells.iter() {
+            if self.grains[*index as usize] == *before {
+                self.set_index(*index as usize, *after);
+            }
+        }
+        self.history.undo.push_back(delta);
+        true
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history = History::default();
+    }
+
+    fn set_index(&mut self, index: usize, cell: Cell) {
+        self.set_cell(index / self.width, index % self.width, cell);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::HISTORY_LIMIT;
+
+    fn settle(t: &mut Tank) {
+        for _ in 0..t.height() * 2 {
+            t.advance_frame();
+        }
+    }
+
+    #[test]
+    fn test_undo_puts_back_what_was_there_before_the_edit() {
+        let mut t = Tank::new(6, 6, 0);
+        t.begin_edit();
+        t.drop_sand(0, 0, 'r', 3);
+        t.end_edit();
+        settle(&mut t);
+        let before = t.to_string();
+
+        t.begin_edit();
+        t.clear();
+        t.end_edit();
+        assert!(t.to_string() == "------\n".repeat(6));
+
+        assert!(t.undo());
+        assert!(t.to_string() == before, "expected \n{} \n actual\n{}", before, t);
+    }
+
+    #[test]
+    fn test_undo_leaves_grains_that_moved_in_since() {
+        let mut t = Tank::new(5, 5, 0);
+        t.begin_edit();
+        t.drop_sand(3, 0, 'g', 5);
+        t.end_edit();
+        t.drop_sand_in_column(2, 'r');
+        settle(&mut t);
+
+        // The r landed on the g edit after it ended, so it stays and falls once the g is gone
+        assert!(t.undo());
+        assert!(t.to_string() == "-----\n-----\n--r--\n-----\n-----\n", "unexpected\n{}", t);
+        settle(&mut t);
+        assert!(t.to_string() == "-----\n-----\n-----\n-----\n--r--\n", "unexpected\n{}", t);
+        assert!(!t.undo(), "there should be nothing left to undo");
+    }
+
+    #[test]
+    fn test_grains_falling_during_an_edit_are_not_part_of_it() {
+        let mut t = Tank::new(6, 6, 0);
+        t.drop_sand(0, 3, 'x', 1);
+        t.begin_edit();
+        t.drop_sand(5, 0, 'g', 1);
+        t.advance_frame();
+        t.end_edit();
+        t.advance_frame();
+        t.advance_frame();
+
+        // Only the g is undone. The x was falling the whole time and is neither lost nor copied
+        assert!(t.undo());
+        assert!(t.grains().count() == 1, "expected only the x left\n{}", t);
+        settle(&mut t);
+        assert!(t.to_string() == format!("{}---x--\n", "------\n".repeat(5)), "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_busy_tanks_only_store_the_edited_cells() {
+        let mut t = Tank::new(40, 40, 0);
+        for i in 0..HISTORY_LIMIT {
+            t.begin_edit();
+            t.drop_sand_in_column(i % 40, 'g');
+            t.end_edit();
+            // Plenty of grains keep falling between edits without being edits themselves
+            for column in (0..40).step_by(3) {
+                t.drop_sand_in_column(column, 'r');
+            }
+            t.advance_frame();
+        }
+
+        assert!(t.history.stored_cells() == HISTORY_LIMIT, "expected one cell per edit, got {}", t.history.stored_cells());
+    }
+
+    #[test]
+    fn test_redo_puts_the_edit_back() {
+        let mut t = Tank::new(5, 5, 0);
+        t.begin_edit();
+        t.drop_sand(3, 0, 'g', 5);
+        t.end_edit();
+        t.begin_edit();
+        t.drop_sand(2, 0, 'r', 5);
+        t.end_edit();
+        settle(&mut t);
+        let after = t.to_string();
+
+        assert!(t.undo() && t.undo());
+        assert!(t.to_string() == "-----\n".repeat(5), "unexpected\n{}", t);
+        assert!(t.redo() && t.redo());
+        assert!(t.to_string() == after, "expected \n{} \n actual\n{}", after, t);
+        assert!(!t.redo());
+    }
+
+    #[test]
+    fn test_a_new_edit_forgets_what_was_undone() {
+        let mut t = Tank::new(4, 4, 0);
+        t.begin_edit();
+        t.drop_sand(3, 0, 'g', 1);
+        t.end_edit();
+        t.undo();
+        assert!(t.can_redo());
+
+        t.begin_edit();
+        t.drop_sand(3, 3, 'r', 1);
+        t.end_edit();
+        assert!(!t.can_redo());
+        assert!(t.undo());
+        assert!(t.to_string() == "----\n".repeat(4), "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_only_the_newest_edits_are_kept() {
+        let mut t = Tank::new(10, 20, 0);
+        for i in 0..HISTORY_LIMIT + 5 {
+            t.begin_edit();
+            t.drop_sand_in_column(i % 10, 'g');
+            t.end_edit();
+            t.advance_frame();
+        }
+
+        let mut undone = 0;
+        while t.undo() {
+            undone += 1;
+        }
+        assert!(undone == HISTORY_LIMIT, "expected {} undos, got {}", HISTORY_LIMIT, undone);
+    }
+}
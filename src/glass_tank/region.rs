@@ -0,0 +1,157 @@
+use super::{Cell, Position, Tank, TankError, EMPTY};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasteMode {
+    // Only the pasted grains are poured, and only into empty cells, like drop_sand
+    FillEmpty,
+    // Every pasted cell replaces the one under it, empty cells included
+    Overwrite,
+}
+
+impl Tank {
+    // The rectangle between two opposite corners as a small tank of its own,
+    // for pasting somewhere else. It keeps this tank's step
+    pub fn copy_region(&self, corner: Position, opposite: Position) -> Result<Tank, TankError> {
+        if let Some(outside) = [corner, opposite].into_iter().find(|position| !self.contains(*position)) {
+            return Err(TankError::OutOfBounds { row: outside.row, column: outside.column, width: self.width, height: self.height });
+        }
+
+        let (top, bottom) = (corner.row.min(opposite.row), corner.row.max(opposite.row));
+        let (left, right) = (corner.column.min(opposite.column), corner.column.max(opposite.column));
+        Ok(self.remapped(right - left + 1, bottom - top + 1, |row, column| (top + row, left + column)))
+    }
+
+    // Turned clockwise a quarter at a time
+    pub fn rotated(&self, quarter_turns: usize) -> Tank {
+        let (width, height) = (self.width, self.height);
+        match quarter_turns % 4 {
+            0 => self.remapped(width, height, |row, column| (row, column)),
+            1 => self.remapped(height, width, |row, column| (height - 1 - column, row)),
+            2 => self.remapped(width, height, |row, column| (height - 1 - row, width - 1 - column)),
+            _ => self.remapped(height, width, |row, column| (column, width - 1 - row))
+        }
+    }
+
+    // Mirrored so the left side ends up on the right
+    pub fn flipped_horizontally(&self) -> Tank {
+        self.remapped(self.width, self.height, |row, column| (row, self.width - 1 - column))
+    }
+
+    // Mirrored so the top ends up at the bottom
+    pub fn flipped_vertically(&self) -> Tank {
+        self.remapped(self.width, self.height, |row, column| (self.height - 1 - row, column))
+    }
+
+    // Copies another tank into this one with its top left cell at `top_left`.
+    // Whatever hangs off the right or bottom is cut off. Returns how many cells changed
+    pub fn paste(&mut self, clipboard: &Tank, top_left: Position, mode: PasteMode) -> usize {
+        let mut changed = 0;
+        for (position, grain) in clipboard.cells() {
+            let cell = grain as Cell;
+            let (Some(row), Some(column)) = (top_left.row.checked_add(position.row), top_left.column.checked_add(position.column)) else {
+                continue;
+            };
+            if row >= self.height || column >= self.width {
+                continue;
+            }
+
+            let current = self.cell(row, column);
+            let paste = match mode {
+                PasteMode::FillEmpty => cell != EMPTY && current == EMPTY,
+                PasteMode::Overwrite => cell != current
+            };
+            if paste {
+                self.set_cell(row, column, cell);
+                changed += 1;
+            }
+        }
+        changed
+    }
+
+    // A new width x height tank where each cell is copied from the (row, column) `source` gives
+    fn remapped(&self, width: usize, height: usize, source: impl Fn(usize, usize) -> (usize, usize)) -> Tank {
+        let mut tank = Tank::new(width, height, self.step);
+        for row in 0..height {
+            for column in 0..width {
+                let (from_row, from_column) = source(row, column);
+                tank.set_cell(row, column, self.cell(from_row, from_column));
+            }
+        }
+        tank
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    fn parse(text: &str) -> Tank {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_copy_region_between_any_corners() {
+        let t = parse("-----\n-abc-\n-def-\n");
+        let copied = t.copy_region(Position::new(2, 3), Position::new(1, 1)).unwrap();
+        assert_eq!(copied, parse("abc\ndef\n"));
+
+        assert!(t.copy_region(Position::new(0, 0), Position::new(3, 0)) == Err(TankError::OutOfBounds { row: 3, column: 0, width: 5, height: 3 }));
+    }
+
+    #[test]
+    fn test_rotate_and_flip() {
+        let t = parse("abc\ndef\n");
+        assert_eq!(t.rotated(0), t);
+        assert_eq!(t.rotated(1), parse("da\neb\nfc\n"));
+        assert_eq!(t.rotated(2), parse("fed\ncba\n"));
+        assert_eq!(t.rotated(3), parse("cf\nbe\nad\n"));
+        assert_eq!(t.rotated(5), t.rotated(1));
+
+        assert_eq!(t.flipped_horizontally(), parse("cba\nfed\n"));
+        assert_eq!(t.flipped_vertically(), parse("def\nabc\n"));
+        assert_eq!(t.flipped_horizontally().flipped_vertically(), t.rotated(2));
+    }
+
+    #[test]
+    fn test_paste_modes() {
+        let clipboard = parse("a-\nbc\n");
+
+        let mut t = parse("----\n-x--\n-xx-\n");
+        assert!(t.paste(&clipboard, Position::new(1, 0), PasteMode::FillEmpty) == 2);
+        assert_eq!(t, parse("----\nax--\nbxx-\n"));
+
+        let mut t = parse("----\n-x--\n-xx-\n");
+        assert!(t.paste(&clipboard, Position::new(1, 0), PasteMode::Overwrite) == 4);
+        assert_eq!(t, parse("----\na---\nbcx-\n"));
+
+        // Only the top left cell fits in the corner
+        assert!(t.paste(&clipboard, Position::new(2, 3), PasteMode::Overwrite) == 1);
+        assert_eq!(t, parse("----\na---\nbcxa\n"));
+    }
+
+    #[test]
+    fn test_paste_off_the_edge() {
+        // Grains already there are kept, and whatever hangs off the bottom right is dropped
+        let mut t = parse("---\n--x\n");
+        assert!(t.paste(&parse("ab\ncd\n"), Position::new(0, 1), PasteMode::FillEmpty) == 3);
+        assert_eq!(t, parse("-ab\n-cx\n"));
+
+        // Positions so far out that adding to them would overflow paste nothing
+        let before = t.clone();
+        assert!(t.paste(&parse("ab\ncd\n"), Position::new(usize::MAX, usize::MAX), PasteMode::Overwrite) == 0);
+        assert!(t.paste(&parse("ab\ncd\n"), Position::new(0, usize::MAX), PasteMode::Overwrite) == 0);
+        assert_eq!(t, before);
+    }
+
+    #[test]
+    fn test_paste_is_one_undo() {
+        let mut t = parse("---\n---\n");
+        t.begin_edit();
+        t.paste(&parse("ab\n"), Position::new(1, 1), PasteMode::FillEmpty);
+        t.end_edit();
+        assert_eq!(t, parse("---\n-ab\n"));
+
+        t.undo();
+        assert_eq!(t, parse("---\n---\n"));
+    }
+}
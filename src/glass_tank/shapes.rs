@@ -0,0 +1,222 @@
+use super::{line_between, try_to_cell, PaintMode, Tank, TankError, EMPTY};
+use std::collections::VecDeque;
+
+// The cells of a rectangle given two opposite corners as (row, column)
+pub fn rectangle_cells(corner: (usize, usize), opposite: (usize, usize), filled: bool) -> Vec<(usize, usize)> {
+    let (top, bottom) = (corner.0.min(opposite.0), corner.0.max(opposite.0));
+    let (left, right) = (corner.1.min(opposite.1), corner.1.max(opposite.1));
+
+    let mut cells = vec![];
+    for row in top..=bottom {
+        for column in left..=right {
+            if filled || row == top || row == bottom || column == left || column == right {
+                cells.push((row, column));
+            }
+        }
+    }
+    cells
+}
+
+// The cells of the ellipse that fits inside the rectangle between two corners.
+// The outline is every cell of the filled ellipse that has a side touching the outside
+pub fn ellipse_cells(corner: (usize, usize), opposite: (usize, usize), filled: bool) -> Vec<(usize, usize)> {
+    let (top, bottom) = (corner.0.min(opposite.0), corner.0.max(opposite.0));
+    let (left, right) = (corner.1.min(opposite.1), corner.1.max(opposite.1));
+    let center_row = (top + bottom) as f32 / 2.0;
+    let center_column = (left + right) as f32 / 2.0;
+    let row_radius = (bottom - top) as f32 / 2.0 + 0.5;
+    let column_radius = (right - left) as f32 / 2.0 + 0.5;
+
+    let inside = |row: isize, column: isize| {
+        let row_distance = (row as f32 - center_row) / row_radius;
+        let column_distance = (column as f32 - center_column) / column_radius;
+        row_distance * row_distance + column_distance * column_distance <= 1.0
+    };
+
+    let mut cells = vec![];
+    for row in top..=bottom {
+        for column in left..=right {
+            let (r, c) = (row as isize, column as isize);
+            if !inside(r, c) {
+                continue;
+            }
+
+            let on_edge = !inside(r - 1, c) || !inside(r + 1, c) || !inside(r, c - 1) || !inside(r, c + 1);
+            if filled || on_edge {
+                cells.push((row, column));
+            }
+        }
+    }
+    cells
+}
+
+impl Tank {
+    // Paints a list of cells, skipping any outside the tank
+    pub fn paint_cells(&mut self, cells: &[(usize, usize)], grain: char, mode: PaintMode) {
+        self.try_paint_cells(cells, grain, mode).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn draw_line(&mut self, from: (usize, usize), to: (usize, usize), grain: char, mode: PaintMode) {
+        self.paint_cells(&line_between(from, to), grain, mode);
+    }
+
+    pub fn draw_rectangle(&mut self, corner: (usize, usize), opposite: (usize, usize), filled: bool, grain: char, mode: PaintMode) {
+        self.paint_cells(&rectangle_cells(corner, opposite, filled), grain, mode);
+    }
+
+    pub fn draw_ellipse(&mut self, corner: (usize, usize), opposite: (usize, usize), filled: bool, grain: char, mode: PaintMode) {
+        self.paint_cells(&ellipse_cells(corner, opposite, filled), grain, mode);
+    }
+
+    // Like paint_cells, but a grain that isn't printable ASCII is an error
+    // instead of a panic, and nothing is painted
+    pub fn try_paint_cells(&mut self, cells: &[(usize, usize)], grain: char, mode: PaintMode) -> Result<(), TankError> {
+        let grain = try_to_cell(grain)?;
+        for (row, column) in cells.iter().copied() {
+            if row >= self.height || column >= self.width {
+                continue;
+            }
+
+            if let Some(cell) = mode.apply(self.cell(row, column), grain) {
+                self.set_cell(row, column, cell);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn try_draw_line(&mut self, from: (usize, usize), to: (usize, usize), grain: char, mode: PaintMode) -> Result<(), TankError> {
+        self.try_paint_cells(&line_between(from, to), grain, mode)
+    }
+
+    pub fn try_draw_rectangle(&mut self, corner: (usize, usize), opposite: (usize, usize), filled: bool, grain: char, mode: PaintMode) -> Result<(), TankError> {
+        self.try_paint_cells(&rectangle_cells(corner, opposite, filled), grain, mode)
+    }
+
+    pub fn try_draw_ellipse(&mut self, corner: (usize, usize), opposite: (usize, usize), filled: bool, grain: char, mode: PaintMode) -> Result<(), TankError> {
+        self.try_paint_cells(&ellipse_cells(corner, opposite, filled), grain, mode)
+    }
+
+    // The empty region connected to a cell through its sides, or nothing if
+    // the cell already has a grain in it
+    pub fn flood_fill_cells(&self, start: (usize, usize)) -> Vec<(usize, usize)> {
+        if start.0 >= self.height || start.1 >= self.width || !self.is_empty(start.0, start.1) {
+            return vec![];
+        }
+
+        let mut seen = vec![false; self.grains.len()];
+        let mut queue = VecDeque::from([start]);
+        let mut cells = vec![];
+        seen[self.index(start.0, start.1)] = true;
+
+        while let Some((row, column)) = queue.pop_front() {
+            cells.push((row, column));
+
+            let neighbors = [
+                (row.wrapping_sub(1), column),
+                (row + 1, column),
+                (row, column.wrapping_sub(1)),
+                (row, column + 1)
+            ];
+            for (row, column) in neighbors {
+                if row >= self.height || column >= self.width {
+                    continue;
+                }
+
+                let index = self.index(row, column);
+                if !seen[index] && self.grains[index] == EMPTY {
+                    seen[index] = true;
+                    queue.push_back((row, column));
+                }
+            }
+        }
+
+        cells
+    }
+
+    // Fills the empty region around a cell with a grain and returns how many cells it filled
+    pub fn flood_fill(&mut self, start: (usize, usize), grain: char) -> usize {
+        self.try_flood_fill(start, grain).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_flood_fill(&mut self, start: (usize, usize), grain: char) -> Result<usize, TankError> {
+        let cells = self.flood_fill_cells(start);
+        self.try_paint_cells(&cells, grain, PaintMode::Fill)?;
+        Ok(cells.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn test_draw_line() {
+        let mut t = Tank::new(4, 3, 0);
+        t.draw_line((2, 0), (0, 3), 'g', PaintMode::Fill);
+        assert!(t.to_string() == "---g\n-gg-\ng---\n", "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_draw_rectangles() {
+        let mut t = Tank::new(5, 4, 0);
+        t.draw_rectangle((3, 4), (0, 0), false, 'g', PaintMode::Fill);
+        assert!(t.to_string() == "ggggg\ng---g\ng---g\nggggg\n", "unexpected\n{}", t);
+
+        t.draw_rectangle((1, 1), (2, 3), true, 'r', PaintMode::Fill);
+        assert!(t.to_string() == "ggggg\ngrrrg\ngrrrg\nggggg\n", "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_draw_ellipses() {
+        let mut t = Tank::new(7, 5, 0);
+        t.draw_ellipse((0, 0), (4, 6), false, 'g', PaintMode::Fill);
+        let expected = Tank::from_grains(vec!(
+            "-ggggg-".to_string(),
+            "g-----g".to_string(),
+            "g-----g".to_string(),
+            "g-----g".to_string(),
+            "-ggggg-".to_string()
+        ));
+        assert_eq!(t, expected);
+
+        let mut filled = Tank::new(7, 5, 0);
+        filled.draw_ellipse((0, 0), (4, 6), true, 'g', PaintMode::Fill);
+        assert!(filled.to_string() == "-ggggg-\nggggggg\nggggggg\nggggggg\n-ggggg-\n", "unexpected\n{}", filled);
+    }
+
+    #[test]
+    fn test_shapes_are_clipped_to_the_tank() {
+        let mut t = Tank::new(3, 3, 0);
+        t.draw_rectangle((1, 1), (10, 10), true, 'g', PaintMode::Fill);
+        assert!(t.to_string() == "---\n-gg\n-gg\n", "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_flood_fill_stays_inside_walls() {
+        let mut t = Tank::from_grains(vec!(
+            "-www-".to_string(),
+            "-w-w-".to_string(),
+            "-w-w-".to_string(),
+            "-www-".to_string()
+        ));
+        let filled = t.flood_fill((1, 2), 's');
+        assert!(filled == 2, "expected 2 cells filled, got {}", filled);
+        assert!(t.to_string() == "-www-\n-wsw-\n-wsw-\n-www-\n", "unexpected\n{}", t);
+
+        assert!(t.flood_fill((3, 2), 's') == 0, "filling from a grain should do nothing");
+    }
+
+    #[test]
+    fn test_bad_grains_are_errors() {
+        let mut t = Tank::new(3, 3, 0);
+        assert!(t.try_draw_line((0, 0), (2, 2), '\n', PaintMode::Fill) == Err(TankError::UnknownSymbol { grain: '\n' }));
+        assert!(t.try_draw_rectangle((0, 0), (2, 2), true, '\u{e9}', PaintMode::Fill) == Err(TankError::UnknownSymbol { grain: '\u{e9}' }));
+        assert!(t.try_draw_ellipse((0, 0), (2, 2), true, ' ', PaintMode::Fill).is_err());
+        assert!(t.try_flood_fill((0, 0), '\u{e9}').is_err());
+        assert!(t.to_string() == "---\n---\n---\n", "failed edits shouldn't change the tank\n{}", t);
+
+        assert!(t.try_draw_line((0, 0), (2, 2), 'g', PaintMode::Fill) == Ok(()));
+        assert!(t.try_flood_fill((2, 0), 'r') == Ok(3));
+        assert!(t.to_string() == "g--\nrg-\nrrg\n", "unexpected\n{}", t);
+    }
+}
@@ -0,0 +1,209 @@
+use super::{grain_name, Tank, EMPTY, MAX_TANK_SIDE};
+use std::fmt;
+
+// A saved tank is a short header followed by the grid, one line per row:
+//
+//     falling-sand tank
+//     version 1
+//     size 5 3
+//     step 1
+//     legend S Beige
+//     legend Y Yellow
+//     grid
+//     -----
+//     --S--
+//     -SYS-
+//
+// `size` is width then height. Every grain in the grid has to be listed in the
+// legend, the name after the symbol is only there for people reading the file.
+// Blank lines and lines starting with `#` are skipped in the header, and keys
+// this version doesn't know about are ignored so newer files can add to it
+pub const TEXT_VERSION: u32 = 1;
+const MAGIC: &str = "falling-sand tank";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextError {
+    // The file doesn't start with the magic line, so it probably isn't a saved tank
+    NotATank,
+    UnsupportedVersion(u32),
+    Missing(&'static str),
+    BadLine { line: usize, message: String },
+    WrongRowCount { expected: usize, found: usize },
+    RaggedRow { line: usize, expected: usize, found: usize },
+    UnknownGrain { line: usize, grain: char },
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextError::NotATank => write!(f, "not a saved tank, the first line should be '{}'", MAGIC),
+            TextError::UnsupportedVersion(version) => write!(f, "version {} is newer than this program understands ({})", version, TEXT_VERSION),
+            TextError::Missing(what) => write!(f, "missing {}", what),
+            TextError::BadLine { line, message } => write!(f, "line {}: {}", line, message),
+            TextError::WrongRowCount { expected, found } => write!(f, "expected {} rows in the grid, found {}", expected, found),
+            TextError::RaggedRow { line, expected, found } => write!(f, "line {}: expected {} cells, found {}", line, expected, found),
+            TextError::UnknownGrain { line, grain } => write!(f, "line {}: {:?} is not in the legend", line, grain),
+        }
+    }
+}
+
+impl std::error::Error for TextError {}
+
+impl Tank {
+    pub fn to_text(&self) -> String {
+        let mut symbols: Vec<u8> = self.grains.iter().copied().filter(|cell| *cell != EMPTY).collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        let mut text = format!("{}\nversion {}\nsize {} {}\nstep {}\n", MAGIC, TEXT_VERSION, self.width, self.height, self.step);
+        for symbol in symbols {
+            let symbol = symbol as char;
+            match grain_name(symbol) {
+                Some(name) => text.push_str(&format!("legend {} {}\n", symbol, name)),
+                None => text.push_str(&format!("legend {}\n", symbol))
+            }
+        }
+        text.push_str("grid\n");
+        text.push_str(&self.to_string());
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Tank, TextError> {
+        let mut lines = text.lines().map(|line| line.trim_end_matches('\r')).enumerate().map(|(i, line)| (i + 1, line));
+        let mut header = lines.by_ref()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+
+        match header.next() {
+            Some((_, line)) if line.trim() == MAGIC => (),
+            _ => return Err(TextError::NotATank)
+        }
+
+        let mut version = None;
+        let mut size = None;
+        let mut step = 0;
+        let mut legend = vec![];
+        loop {
+            let Some((number, line)) = header.next() else {
+                return Err(TextError::Missing("grid"));
+            };
+            let bad_line = |message: &str| TextError::BadLine { line: number, message: message.to_string() };
+
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("grid") => break,
+                Some("version") => {
+                    let found = words.next().and_then(|word| word.parse().ok())
+                        .ok_or_else(|| bad_line("expected `version <number>`"))?;
+                    if found > TEXT_VERSION {
+                        return Err(TextError::UnsupportedVersion(found));
+                    }
+                    version = Some(found);
+                },
+                Some("size") => {
+                    let width: usize = words.next().and_then(|word| word.parse().ok())
+                        .ok_or_else(|| bad_line("expected `size <width> <height>`"))?;
+                    let height: usize = words.next().and_then(|word| word.parse().ok())
+                        .ok_or_else(|| bad_line("expected `size <width> <height>`"))?;
+                    if width == 0 || height == 0 {
+                        return Err(bad_line("the tank needs a width and height of at least 1"));
+                    }
+                    if width > MAX_TANK_SIDE || height > MAX_TANK_SIDE || width.checked_mul(height).is_none() {
+                        return Err(bad_line(&format!("the tank can be at most {} cells on a side", MAX_TANK_SIDE)));
+                    }
+                    size = Some((width, height));
+                },
+                Some("step") => {
+                    step = words.next().and_then(|word| word.parse().ok())
+                        .ok_or_else(|| bad_line("expected `step <number>`"))?;
+                },
+                Some("legend") => {
+                    let grain = words.next().and_then(|word| {
+                        let mut chars = word.chars();
+                        chars.next().filter(|c| chars.next().is_none() && c.is_ascii_graphic() && *c != EMPTY as char)
+                    });
+                    legend.push(grain.ok_or_else(|| bad_line("expected `legend <symbol> [name]` with a single character symbol"))?);
+                },
+                _ => ()
+            }
+        }
+
+        version.ok_or(TextError::Missing("version"))?;
+        let (width, height) = size.ok_or(TextError::Missing("size"))?;
+
+        let mut tank = Tank::new(width, height, step);
+        let mut rows = 0;
+        for (number, line) in lines {
+            if rows == height {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return Err(TextError::WrongRowCount { expected: height, found: rows + 1 });
+            }
+
+            let found = line.chars().count();
+            if found != width {
+                return Err(TextError::RaggedRow { line: number, expected: width, found });
+            }
+            for (column, grain) in line.chars().enumerate() {
+                if grain == EMPTY as char {
+                    continue;
+                }
+                if !legend.contains(&grain) {
+                    return Err(TextError::UnknownGrain { line: number, grain });
+                }
+                tank.set_cell(rows, column, grain as u8);
+            }
+            rows += 1;
+        }
+
+        if rows != height {
+            return Err(TextError::WrongRowCount { expected: height, found: rows });
+        }
+        Ok(tank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn test_text_round_trip_keeps_the_step() {
+        let mut t = Tank::from_grains(vec!(
+            "--S--".to_string(),
+            "-SYS-".to_string()
+        ));
+        t.set_step(2);
+
+        let text = t.to_text();
+        assert!(text == "falling-sand tank\nversion 1\nsize 5 2\nstep 2\nlegend S Beige\nlegend Y Yellow\ngrid\n--S--\n-SYS-\n", "unexpected\n{}", text);
+
+        let loaded = Tank::from_text(&text).unwrap();
+        assert_eq!(loaded, t);
+        assert!(loaded.step() == 2);
+    }
+
+    #[test]
+    fn test_text_header_skips_comments_and_unknown_keys() {
+        let text = "# shared scene\nfalling-sand tank\nversion 1\n\nsize 3 2\nauthor someone\nlegend g\ngrid\n---\ng-g\n";
+        let t = Tank::from_text(text).unwrap();
+        assert!(t.to_string() == "---\ng-g\n" && t.step() == 0, "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_bad_text_is_an_error() {
+        let header = "falling-sand tank\nversion 1\nsize 3 2\nlegend g\ngrid\n";
+        assert!(matches!(Tank::from_text("---\n---\n"), Err(TextError::NotATank)));
+        assert!(matches!(Tank::from_text("falling-sand tank\nversion 2\n"), Err(TextError::UnsupportedVersion(2))));
+        assert!(matches!(Tank::from_text("falling-sand tank\nsize 3 2\ngrid\n---\n---\n"), Err(TextError::Missing("version"))));
+        assert!(matches!(Tank::from_text(&format!("{}---\n--\n", header)), Err(TextError::RaggedRow { line: 7, expected: 3, found: 2 })));
+        assert!(matches!(Tank::from_text(&format!("{}---\n", header)), Err(TextError::WrongRowCount { expected: 2, found: 1 })));
+        assert!(matches!(Tank::from_text(&format!("{}---\n-r-\n", header)), Err(TextError::UnknownGrain { line: 7, grain: 'r' })));
+
+        // Sizes are checked before anything is allocated for them
+        for size in ["4000000000 4000000000", "200000 200000", "8193 1", "1 8193"] {
+            let text = format!("falling-sand tank\nversion 1\nsize {}\ngrid\n", size);
+            assert!(matches!(Tank::from_text(&text), Err(TextError::BadLine { line: 3, .. })), "size {} should be refused", size);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::glass_tank::{Tank, TankError};
+use crate::palette::{GrainType, GRAIN_TYPES};
+use std::fmt;
+
+// Pixels less opaque than this are left empty
+const ALPHA_CUTOFF: f32 = 128.0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    EmptyImage { width: usize, height: usize },
+    // The pixel buffer doesn't hold image_width * image_height pixels
+    WrongPixelCount { expected: usize, found: usize },
+    // The tank it was going to become couldn't be made, like one with no cells
+    Tank(TankError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::EmptyImage { width, height } => write!(f, "the image is {}x{}, it has no pixels to import", width, height),
+            ImportError::WrongPixelCount { expected, found } => write!(f, "expected {} pixels, got {}", expected, found),
+            ImportError::Tank(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+// Turns an image into a tank, one grain per cell in whichever palette color is
+// closest. The image is scaled to the tank size, each cell taking the average
+// of the pixels it covers. With dithering the error from rounding every cell to
+// a palette color is spread onto its neighbors (Floyd-Steinberg), so shades in
+// between palette colors come out as a mix of grains instead of flat bands
+pub fn tank_from_pixels(pixels: &[[u8; 4]], image_width: usize, image_height: usize, width: usize, height: usize, dither: bool) -> Result<Tank, ImportError> {
+    if image_width == 0 || image_height == 0 {
+        return Err(ImportError::EmptyImage { width: image_width, height: image_height });
+    }
+    match image_width.checked_mul(image_height) {
+        Some(expected) if expected == pixels.len() => (),
+        expected => return Err(ImportError::WrongPixelCount { expected: expected.unwrap_or(usize::MAX), found: pixels.len() })
+    }
+    let mut tank = Tank::try_new(width, height, 0).map_err(ImportError::Tank)?;
+
+    let mut colors = scale(pixels, image_width, image_height, width, height);
+
+    for row in 0..height {
+        for column in 0..width {
+            let [r, g, b, a] = colors[row * width + column];
+            if a < ALPHA_CUTOFF {
+                continue;
+            }
+
+            let grain_type = nearest_grain(r, g, b);
+            tank.drop_sand(row, column, grain_type.symbol, 1);
+            if !dither {
+                continue;
+            }
+
+            let [gr, gg, gb, _] = grain_type.color;
+            let error = [r - gr as f32, g - gg as f32, b - gb as f32];
+            let neighbors = [(0, 1, 7.0), (1, -1, 3.0), (1, 0, 5.0), (1, 1, 1.0)];
+            for (row_offset, column_offset, weight) in neighbors {
+                let (next_row, next_column) = (row + row_offset, column as isize + column_offset);
+                if next_row >= height || next_column < 0 || next_column as usize >= width {
+                    continue;
+                }
+
+                let next = &mut colors[next_row * width + next_column as usize];
+                for channel in 0..3 {
+                    next[channel] += error[channel] * weight / 16.0;
+                }
+            }
+        }
+    }
+
+    Ok(tank)
+}
+
+// Averages the pixels under each cell, which shrinks an image smoothly and
+// repeats pixels when it has to grow
+fn scale(pixels: &[[u8; 4]], image_width: usize, image_height: usize, width: usize, height: usize) -> Vec<[f32; 4]> {
+    let mut colors = Vec::with_capacity(width * height);
+    for row in 0..height {
+        let top = row * image_height / height;
+        let bottom = ((row + 1) * image_height / height).max(top + 1);
+
+        for column in 0..width {
+            let left = column * image_width / width;
+            let right = ((column + 1) * image_width / width).max(left + 1);
+
+            // Colors are weighted by alpha so transparent pixels don't darken the edges
+            let mut sum = [0.0; 4];
+            for y in top..bottom {
+                for x in left..right {
+                    let [r, g, b, a] = pixels[y * image_width + x];
+                    let alpha = a as f32 / 255.0;
+                    sum[0] += r as f32 * alpha;
+                    sum[1] += g as f32 * alpha;
+                    sum[2] += b as f32 * alpha;
+                    sum[3] += a as f32;
+                }
+            }
+
+            let count = ((bottom - top) * (right - left)) as f32;
+            let opacity = (sum[3] / 255.0).max(f32::EPSILON);
+            colors.push([sum[0] / opacity, sum[1] / opacity, sum[2] / opacity, sum[3] / count]);
+        }
+    }
+    colors
+}
+
+fn nearest_grain(r: f32, g: f32, b: f32) -> &'static GrainType {
+    let distance = |grain_type: &GrainType| {
+        let [gr, gg, gb, _] = grain_type.color;
+        (r - gr as f32).powi(2) + (g - gg as f32).powi(2) + (b - gb as f32).powi(2)
+    };
+    GRAIN_TYPES.iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .unwrap_or(&GRAIN_TYPES[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [230, 40, 50, 255];
+    const BLUE: [u8; 4] = [0, 110, 250, 255];
+    const CLEAR: [u8; 4] = [255, 255, 255, 0];
+
+    #[test]
+    fn test_pixels_become_the_nearest_grain() {
+        let pixels = [RED, CLEAR, BLUE, [250, 250, 250, 200]];
+        let t = tank_from_pixels(&pixels, 2, 2, 2, 2, false).unwrap();
+        assert!(t.to_string() == "R-\nBW\n", "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_images_are_scaled_to_the_tank() {
+        // Left half red, right half blue, and the bottom row see-through
+        let mut pixels = vec![];
+        for y in 0..4 {
+            for x in 0..8 {
+                pixels.push(if y == 3 { CLEAR } else if x < 4 { RED } else { BLUE });
+            }
+        }
+
+        let t = tank_from_pixels(&pixels, 8, 4, 4, 2, false).unwrap();
+        assert!(t.to_string() == "RRBB\n----\n", "unexpected\n{}", t);
+
+        let t = tank_from_pixels(&pixels, 8, 4, 16, 8, false).unwrap();
+        assert!(t.to_string().lines().next() == Some("RRRRRRRRBBBBBBBB"), "unexpected\n{}", t);
+        assert!(t.to_string().lines().last() == Some("----------------"), "unexpected\n{}", t);
+    }
+
+    #[test]
+    fn test_bad_sizes_are_errors() {
+        assert!(tank_from_pixels(&[], 0, 0, 3, 3, false) == Err(ImportError::EmptyImage { width: 0, height: 0 }));
+        assert!(tank_from_pixels(&[RED; 3], 2, 2, 3, 3, false) == Err(ImportError::WrongPixelCount { expected: 4, found: 3 }));
+        assert!(tank_from_pixels(&[RED; 3], usize::MAX, 2, 3, 3, false) == Err(ImportError::WrongPixelCount { expected: usize::MAX, found: 3 }));
+        assert!(tank_from_pixels(&[RED; 4], 2, 2, 0, 3, false) == Err(ImportError::Tank(TankError::ZeroSize { width: 0, height: 3 })));
+    }
+
+    #[test]
+    fn test_dithering_mixes_grains_for_in_between_colors() {
+        // Halfway between white and blue isn't close to any one grain
+        let pixels = vec![[128, 187, 248, 255]; 64];
+        let flat = tank_from_pixels(&pixels, 8, 8, 8, 8, false).unwrap().to_string();
+        let dithered = tank_from_pixels(&pixels, 8, 8, 8, 8, true).unwrap().to_string();
+
+        let kinds = |text: &str| {
+            let mut symbols: Vec<char> = text.chars().filter(|c| *c != '\n').collect();
+            symbols.sort();
+            symbols.dedup();
+            symbols.len()
+        };
+        assert!(kinds(&flat) == 1, "expected one grain\n{}", flat);
+        assert!(kinds(&dithered) > 1, "expected a mix of grains\n{}", dithered);
+    }
+}
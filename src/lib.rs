@@ -0,0 +1,21 @@
+// The falling sand simulation without a window. Tanks can be built, edited,
+// advanced, saved and recorded from any program, and the viewer in main.rs is
+// just one user of this. Everything a program needs is here at the top, the
+// modules behind it are free to change
+mod capture;
+mod glass_tank;
+mod import;
+mod palette;
+mod recorder;
+#[cfg(test)]
+mod test_support;
+
+pub use capture::{numbered_path, tank_pixels, write_png};
+pub use glass_tank::{
+    ellipse_cells, grain_name, line_between, rectangle_cells,
+    Anchor, BinaryError, Brush, BrushShape, Cell, Neighborhood, PaintMode, PasteMode, Position, Schedule, Tank, TankError, TextError,
+    BINARY_MAGIC, BINARY_VERSION, EMPTY, GRAIN_NAMES, MAX_TANK_SIDE, TEXT_VERSION,
+};
+pub use import::{tank_from_pixels, ImportError};
+pub use palette::{color_of, GrainType, GRAIN_TYPES};
+pub use recorder::{RecordFormat, Recorder};
@@ -1,84 +1,528 @@
+use falling_sand::{color_of, ellipse_cells, line_between, numbered_path, rectangle_cells, tank_from_pixels, tank_pixels, write_png, Anchor, Brush, BrushShape, Cell, PaintMode, PasteMode, Position, Recorder, Schedule, Tank, TextError, BINARY_MAGIC, GRAIN_TYPES, MAX_TANK_SIDE};
 use macroquad::prelude::*;
-pub mod glass_tank;
+use settings::{Settings, SettingsError};
+use std::time::{Duration, Instant};
+mod renderer;
+mod playback;
+mod settings;
+mod toolbar;
+mod view;
 
-#[macroquad::main("Falling Sand")]
-async fn main() {
-    let screen_width = 150;
-    let screen_height = 200;
-    let area_of_sand_drop = 10;
+fn main() {
+    let settings = match Settings::from_args(std::env::args().skip(1)) {
+        Ok(settings) => settings,
+        Err(SettingsError::HelpRequested) => {
+            println!("{}", settings::USAGE);
+            return;
+        },
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, settings::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let loaded = match (&settings.scene, &settings.image) {
+        (Some(path), _) => load_scene(path, settings.step),
+        (None, Some(path)) => load_image(path, &settings),
+        (None, None) => Ok(Tank::new(settings.width, settings.height, settings.step))
+    };
+    let mut tank = match loaded {
+        Ok(tank) => tank,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        }
+    };
+    if settings.threads > 1 {
+        tank.set_schedule(Schedule::Checkerboard { threads: settings.threads });
+    }
+
+    let window_width = tank.width() as f32 * settings.cell_size;
+    let conf = Conf {
+        window_title: "Falling Sand".to_owned(),
+        window_width: window_width as i32,
+        window_height: (tank.height() as f32 * settings.cell_size + toolbar::Toolbar::height(window_width)) as i32,
+        window_resizable: true,
+        ..Default::default()
+    };
+    macroquad::Window::from_config(conf, run(settings, tank));
+}
+
+// Reads a saved tank in either the binary or the text format. Text files
+// without the header are read as plain lines of grains, like Tank::to_string
+// writes, and get the step from the settings
+fn load_scene(path: &str, step: usize) -> Result<Tank, String> {
+    let bytes = std::fs::read(path).map_err(|error| format!("could not read {}: {}", path, error))?;
+    if bytes.starts_with(BINARY_MAGIC) {
+        return Tank::from_bytes(&bytes).map_err(|error| format!("{}: {}", path, error));
+    }
+
+    let text = String::from_utf8(bytes).map_err(|_| format!("{} is not a saved tank", path))?;
+    match Tank::from_text(&text) {
+        Ok(tank) => return Ok(tank),
+        Err(TextError::NotATank) => (),
+        Err(error) => return Err(format!("{}: {}", path, error))
+    }
+
+    // Short rows are padded with empty cells, the same as Tank::from_grains does
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+    if width > MAX_TANK_SIDE || lines.len() > MAX_TANK_SIDE {
+        return Err(format!("{}: {}x{} is bigger than the largest tank, {}x{}", path, width, lines.len(), MAX_TANK_SIDE, MAX_TANK_SIDE));
+    }
+
+    let padded = lines.iter().map(|line| format!("{:-<width$}", line, width = width));
+    let mut tank = Tank::try_from_grains(padded).map_err(|error| format!("{}: {}", path, error))?;
+    tank.set_step(step);
+    Ok(tank)
+}
+
+// Reads a picture in any format macroquad can decode and turns it into a tank the size from the settings
+fn load_image(path: &str, settings: &Settings) -> Result<Tank, String> {
+    let bytes = std::fs::read(path).map_err(|error| format!("could not read {}: {}", path, error))?;
+    let image = Image::from_file_with_format(&bytes, None).map_err(|error| format!("could not decode {}: {}", path, error))?;
+
+    let pixels: Vec<[u8; 4]> = image.bytes.chunks_exact(4).map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]]).collect();
+    let mut tank = tank_from_pixels(&pixels, image.width as usize, image.height as usize, settings.width, settings.height, settings.dither)
+        .map_err(|error| format!("could not import {}: {}", path, error))?;
+    tank.set_step(settings.step);
+    Ok(tank)
+}
+
+// Files ending in .txt are saved as text so they can be read and edited, anything else is binary
+fn save_scene(tank: &Tank, path: &str) -> Result<(), String> {
+    let bytes = if path.ends_with(".txt") {
+        tank.to_text().into_bytes()
+    } else {
+        tank.to_bytes()
+    };
+    std::fs::write(path, bytes).map_err(|error| format!("could not write {}: {}", path, error))
+}
+
+// Saves either the whole window or just the tank, straight from its cells, to
+// the next free numbered file in the screenshot folder
+fn take_screenshot(settings: &Settings, tank: Option<&Tank>) -> Result<std::path::PathBuf, String> {
+    let folder = std::path::Path::new(&settings.screenshot_dir);
+    std::fs::create_dir_all(folder).map_err(|error| format!("could not create {}: {}", folder.display(), error))?;
+
+    let (path, width, height, pixels) = match tank {
+        Some(tank) => {
+            let scale = settings.screenshot_scale;
+            let path = numbered_path(folder, "tank", "png");
+            (path, tank.width() * scale, tank.height() * scale, tank_pixels(tank, scale))
+        },
+        None => {
+            // The screen comes back bottom row first
+            let screen = get_screen_data();
+            let row_bytes = screen.width as usize * 4;
+            let pixels: Vec<u8> = screen.bytes.chunks_exact(row_bytes).rev().flatten().copied().collect();
+            let path = numbered_path(folder, "screenshot", "png");
+            (path, screen.width as usize, screen.height as usize, pixels)
+        }
+    };
+
+    write_png(&path, width, height, &pixels).map_err(|error| format!("could not write {}: {}", path.display(), error))?;
+    Ok(path)
+}
+
+fn stop_recording(recorder: &mut Option<Recorder>) -> String {
+    match recorder.take().map(|recording| recording.finish()) {
+        Some(Ok(path)) => format!("saved {}", path.display()),
+        Some(Err(error)) => format!("could not finish recording: {}", error),
+        None => String::new()
+    }
+}
+
+const MAX_BRUSH_SIZE: usize = 200;
+
+fn draw_brush_outline(brush: &Brush, view: &view::View, center: Position) {
+    let scale = view.scale();
+    let first = (brush.size as f32 - 1.0) / 2.0;
+    let x = view.origin.x + (center.column as f32 - first.floor()) * scale;
+    let y = view.origin.y + (center.row as f32 - first.floor()) * scale;
+    let size = brush.size as f32 * scale;
 
-                                                         // Magic number to fix window height
-    request_new_screen_size(screen_width as f32, screen_height as f32 + 28.0);
-    next_frame().await;
+    match brush.shape {
+        BrushShape::Square => draw_rectangle_lines(x, y, size, size, 1.0, LIGHTGRAY),
+        BrushShape::Circle => draw_circle_lines(x + size / 2.0, y + size / 2.0, size / 2.0, 1.0, LIGHTGRAY)
+    }
+}
+
+// The cells a shape tool would paint if the drag ended at `to`
+fn shape_cells(tool: toolbar::Tool, tank: &Tank, from: Position, to: Position, filled: bool) -> Vec<Position> {
+    let cells = match tool {
+        toolbar::Tool::Line => line_between(from.into(), to.into()),
+        toolbar::Tool::Rectangle => rectangle_cells(from.into(), to.into(), filled),
+        toolbar::Tool::Ellipse => ellipse_cells(from.into(), to.into(), filled),
+        toolbar::Tool::Fill => tank.flood_fill_cells(to.into()),
+        _ => vec![]
+    };
+    cells.into_iter().map(Position::from).collect()
+}
+
+fn draw_preview(cells: &[Position], grain: char, view: &view::View) {
+    let [r, g, b, _] = color_of(grain as Cell);
+    let color = Color::from_rgba(r, g, b, 160);
+    let scale = view.scale();
+    for cell in cells {
+        draw_rectangle(view.origin.x + cell.column as f32 * scale, view.origin.y + cell.row as f32 * scale, scale, scale, color);
+    }
+}
+
+fn draw_selection(corner: Position, opposite: Position, view: &view::View) {
+    let (top, left) = (corner.row.min(opposite.row), corner.column.min(opposite.column));
+    let rows = corner.row.abs_diff(opposite.row) + 1;
+    let columns = corner.column.abs_diff(opposite.column) + 1;
+    let scale = view.scale();
+    draw_rectangle_lines(view.origin.x + left as f32 * scale, view.origin.y + top as f32 * scale, columns as f32 * scale, rows as f32 * scale, 1.0, WHITE);
+}
+
+// The copied region where it would be pasted, with its top left cell under the cursor
+fn draw_clipboard(clipboard: &Tank, top_left: Position, view: &view::View) {
+    let scale = view.scale();
+    for (position, grain) in clipboard.grains() {
+        let [r, g, b, _] = color_of(grain as Cell);
+        let x = view.origin.x + (top_left.column + position.column) as f32 * scale;
+        let y = view.origin.y + (top_left.row + position.row) as f32 * scale;
+        draw_rectangle(x, y, scale, scale, Color::from_rgba(r, g, b, 160));
+    }
+    draw_rectangle_lines(view.origin.x + top_left.column as f32 * scale, view.origin.y + top_left.row as f32 * scale, clipboard.width() as f32 * scale, clipboard.height() as f32 * scale, 1.0, LIGHTGRAY);
+}
+
+async fn run(settings: Settings, mut tank: Tank) {
+    let mut brush = Brush::new(BrushShape::Circle, settings.brush_size)
+        .with_seed(macroquad::miniquad::date::now().to_bits());
+    let mut last_painted: Option<Position> = None;
+    // Where a shape being dragged out started and where the cursor last was over the tank
+    let mut shape_drag: Option<(Position, Position)> = None;
+    // The last region copied with the select tool, ready to paste
+    let mut clipboard: Option<Tank> = None;
+    let frame_time = (settings.frame_rate > 0).then(|| Duration::from_secs_f64(1.0 / settings.frame_rate as f64));
 
-    let mut tank = glass_tank::Tank::new(screen_width, screen_height, 0);
+    let mut playback = playback::Playback::new(settings.tick_rate, settings.max_catch_up);
+    // A picture is left standing until Space is pressed, so there's a chance to see it before it collapses
+    if settings.image.is_some() {
+        playback.toggle_pause();
+    }
+    let mut renderer = renderer::TankRenderer::new(&tank);
+    let mut toolbar = toolbar::Toolbar::new();
+    let mut view = view::View::new(settings.cell_size);
+    view.reset((tank.width(), tank.height()), toolbar.tank_area(vec2(screen_width(), screen_height())));
+    let mut last_mouse = Vec2::from(mouse_position());
+    let mut last_screen_size = vec2(screen_width(), screen_height());
+    // Shown in the status line for a few seconds, like "saved tank.txt"
+    let mut message: Option<(String, Instant)> = None;
+    let mut recorder: Option<Recorder> = None;
 
     loop {
+        let frame_start = Instant::now();
         clear_background(BLACK);
 
-        let mouse_pos = mouse_position();
-        
-        if mouse_pos.0 > 0.0 && mouse_pos.0 < screen_width as f32 &&
-           mouse_pos.1 > 0.0 && mouse_pos.1 < screen_height as f32 {
-            if is_key_down(KeyCode::G) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'G', area_of_sand_drop); // Green
-            } else if is_key_down(KeyCode::R) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'R', area_of_sand_drop); // Red
-            } else if is_key_down(KeyCode::B) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'B', area_of_sand_drop); // Blue
-            } else if is_key_down(KeyCode::P) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'P', area_of_sand_drop); // Pink
-            } else if is_key_down(KeyCode::U) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'U', area_of_sand_drop); // Purple
-            } else if is_key_down(KeyCode::C) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'C', area_of_sand_drop); // Cyan
-            } else if is_key_down(KeyCode::Y) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'Y', area_of_sand_drop); // Yellow
-            } else if is_key_down(KeyCode::O) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'O', area_of_sand_drop); // Orange
-            } else if is_key_down(KeyCode::M) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'M', area_of_sand_drop); // Maroon
-            } else if is_key_down(KeyCode::W) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'W', area_of_sand_drop); // White
-            } else if is_key_down(KeyCode::F) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'F', area_of_sand_drop); // Fuscia
-            } else if is_key_down(KeyCode::S) {
-                tank.drop_sand(mouse_pos.1 as usize, mouse_pos.0 as usize, 'S', area_of_sand_drop); // Sand Color (Beige)
-            } 
-        }
-
-        for row in 0..tank.grains.len() {
-            for column in 0..tank.grains[row].len() {
-                if tank.grains[row][column] == '-' {
-                    continue;
+        // The tank grows and shrinks with the window, keeping its floor where it is.
+        // Past MAX_TANK_SIDE cells a side it stops growing and the rest of the window stays empty
+        let screen_size = vec2(screen_width(), screen_height());
+        let tank_area = toolbar.tank_area(screen_size);
+        if screen_size != last_screen_size {
+            let width = ((tank_area.w / settings.cell_size) as usize).clamp(1, MAX_TANK_SIDE);
+            let height = ((tank_area.h / settings.cell_size) as usize).clamp(1, MAX_TANK_SIDE);
+            if (width, height) != (tank.width(), tank.height()) {
+                tank.resize(width, height, Anchor::Bottom);
+                renderer = renderer::TankRenderer::new(&tank);
+                view.reset((width, height), tank_area);
+            }
+            last_screen_size = screen_size;
+        }
+
+        let tank_size = (tank.width(), tank.height());
+        let mouse = Vec2::from(mouse_position());
+
+        // Scroll to change the brush size, or hold Ctrl and scroll to zoom around the cursor.
+        // Drag with the right or middle button to pan
+        let wheel = mouse_wheel().1;
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        if wheel != 0.0 && ctrl {
+            view.zoom_at(mouse, if wheel > 0.0 { 1.25 } else { 0.8 });
+        } else if wheel > 0.0 {
+            brush.size = (brush.size + 1).min(MAX_BRUSH_SIZE);
+        } else if wheel < 0.0 {
+            brush.size = (brush.size - 1).max(1);
+        }
+        if is_mouse_button_down(MouseButton::Right) || is_mouse_button_down(MouseButton::Middle) {
+            view.pan(mouse - last_mouse);
+        }
+        if is_key_pressed(KeyCode::Home) {
+            view.reset(tank_size, tank_area);
+        }
+        last_mouse = mouse;
+
+        let over_toolbar = toolbar.handle_mouse(mouse, screen_size.x);
+        toolbar.handle_keys();
+        brush.mode = match toolbar.tool {
+            toolbar::Tool::Replace => PaintMode::Replace,
+            toolbar::Tool::Eraser => PaintMode::Erase,
+            _ => PaintMode::Fill
+        };
+        if is_key_pressed(KeyCode::Delete) {
+            tank.begin_edit();
+            tank.clear();
+            tank.end_edit();
+        }
+
+        // F5 saves the tank and F9 loads it back, replacing whatever is there
+        if is_key_pressed(KeyCode::F5) {
+            let text = match save_scene(&tank, &settings.save_file) {
+                Ok(()) => format!("saved {}", settings.save_file),
+                Err(error) => error
+            };
+            message = Some((text, Instant::now()));
+        }
+        if is_key_pressed(KeyCode::F9) {
+            let text = match load_scene(&settings.save_file, settings.step) {
+                Ok(loaded) => {
+                    tank = loaded;
+                    if settings.threads > 1 {
+                        tank.set_schedule(Schedule::Checkerboard { threads: settings.threads });
+                    }
+                    renderer = renderer::TankRenderer::new(&tank);
+                    view.reset((tank.width(), tank.height()), tank_area);
+                    last_painted = None;
+                    shape_drag = None;
+                    format!("loaded {}", settings.save_file)
+                },
+                Err(error) => error
+            };
+            message = Some((text, Instant::now()));
+        }
+
+        // Ctrl+Z undoes the last stroke, shape or clear and Ctrl+Shift+Z puts it back
+        if ctrl && is_key_pressed(KeyCode::Z) {
+            let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+            if shift {
+                tank.redo();
+            } else {
+                tank.undo();
+            }
+            last_painted = None;
+            shape_drag = None;
+        }
+
+        // Space pauses, `.` advances a single frame and the up and down arrows change the speed.
+        // Painting still works while paused
+        if is_key_pressed(KeyCode::Space) {
+            playback.toggle_pause();
+        }
+        if is_key_pressed(KeyCode::Period) {
+            playback.step();
+        }
+        if is_key_pressed(KeyCode::Up) {
+            playback.faster();
+        }
+        if is_key_pressed(KeyCode::Down) {
+            playback.slower();
+        }
+
+        if is_key_pressed(KeyCode::Tab) {
+            brush.shape = match brush.shape {
+                BrushShape::Circle => BrushShape::Square,
+                BrushShape::Square => BrushShape::Circle
+            };
+        }
+        if is_key_pressed(KeyCode::Minus) {
+            brush.density = (brush.density - 0.1).max(0.1);
+        }
+        if is_key_pressed(KeyCode::Equal) {
+            brush.density = (brush.density + 0.1).min(1.0);
+        }
+        if is_key_pressed(KeyCode::LeftBracket) {
+            brush.scatter = brush.scatter.saturating_sub(1);
+        }
+        if is_key_pressed(KeyCode::RightBracket) {
+            brush.scatter += 1;
+        }
+
+        // Holding a grain's key drops it and selects it, otherwise the left button drops the selected grain
+        let held_grain = GRAIN_TYPES.iter()
+            .map(|grain_type| grain_type.symbol)
+            .find(|symbol| toolbar::grain_key(*symbol).is_some_and(is_key_down));
+        if let Some(symbol) = held_grain {
+            toolbar.selected = symbol;
+        }
+        let painting = toolbar.tool.uses_brush() && (held_grain.is_some() || is_mouse_button_down(MouseButton::Left));
+
+        // Strokes join up with where the brush was last frame so fast movements don't leave gaps.
+        // A whole stroke is one edit as far as undo is concerned
+        let cursor_cell = view.cell_at(mouse, tank_size).filter(|_| !over_toolbar);
+        match (painting, cursor_cell) {
+            (true, Some(cell)) => {
+                tank.begin_edit();
+                tank.paint_stroke(&mut brush, last_painted.unwrap_or(cell).into(), cell.into(), toolbar.selected);
+                last_painted = Some(cell);
+            },
+            _ => {
+                tank.end_edit();
+                last_painted = None;
+            }
+        }
+
+        // Shapes are dragged out with the left button and previewed until it's let go.
+        // Holding Shift fills rectangles and ellipses in
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let mut preview = vec![];
+        let mut selection = None;
+        let pasting = toolbar.tool == toolbar::Tool::Select && clipboard.is_some();
+        if toolbar.tool.uses_brush() || pasting {
+            shape_drag = None;
+        } else {
+            if let (Some(cell), true) = (cursor_cell, is_mouse_button_pressed(MouseButton::Left)) {
+                shape_drag = Some((cell, cell));
+            }
+            if let (Some((_, end)), Some(cell)) = (shape_drag.as_mut(), cursor_cell) {
+                *end = cell;
+            }
+            if let Some((start, end)) = shape_drag {
+                let released = !is_mouse_button_down(MouseButton::Left);
+                if toolbar.tool == toolbar::Tool::Select {
+                    selection = Some((start, end));
+                    if released {
+                        let text = match tank.copy_region(start, end) {
+                            Ok(copied) => {
+                                let text = format!("copied {}x{}", copied.width(), copied.height());
+                                clipboard = Some(copied);
+                                text
+                            },
+                            Err(error) => error.to_string()
+                        };
+                        message = Some((text, Instant::now()));
+                    }
+                } else {
+                    let cells = shape_cells(toolbar.tool, &tank, start, end, shift);
+                    if released {
+                        tank.begin_edit();
+                        let cells: Vec<(usize, usize)> = cells.into_iter().map(Position::into).collect();
+                        tank.paint_cells(&cells, toolbar.selected, PaintMode::Fill);
+                        tank.end_edit();
+                    } else {
+                        preview = cells;
+                    }
+                }
+                if released {
+                    shape_drag = None;
                 }
+            }
+        }
 
-                let color = match tank.grains[row][column] {
-                    'P' => PINK,
-                    'F' => MAGENTA,
-                    'R' => RED,
-                    'M' => MAROON,
-                    'O' => ORANGE,
-                    'S' => BEIGE,
-                    'Y' => YELLOW,
-                    'G' => GREEN,
-                    'C' => SKYBLUE,
-                    'B' => BLUE,
-                    'U' => PURPLE,
-                    'W' => WHITE,
-                    _ => BLACK
-                };
-
-                draw_rectangle(column as f32, row as f32, 1.0, 1.0, color);
+        // Once something is copied the select tool carries it around under the cursor.
+        // A click pastes it into the empty cells, or over everything with Shift held.
+        // E turns it clockwise, H and V flip it and Escape puts it down to select again
+        if let (Some(copied), true) = (clipboard.as_mut(), pasting) {
+            if is_key_pressed(KeyCode::E) {
+                *copied = copied.rotated(1);
             }
+            if is_key_pressed(KeyCode::H) {
+                *copied = copied.flipped_horizontally();
+            }
+            if is_key_pressed(KeyCode::V) {
+                *copied = copied.flipped_vertically();
+            }
+            if let (Some(cell), true) = (cursor_cell, is_mouse_button_pressed(MouseButton::Left)) {
+                let mode = if shift { PasteMode::Overwrite } else { PasteMode::FillEmpty };
+                tank.begin_edit();
+                tank.paste(copied, cell, mode);
+                tank.end_edit();
+            }
+            if is_key_pressed(KeyCode::Escape) {
+                clipboard = None;
+            }
+        }
+
+        // The tank is advanced at a fixed tick rate, however often the window is drawn
+        let ticks = playback.frames_to_advance(get_frame_time() as f64);
+        if settings.interpolate && ticks > 0 {
+            renderer.keep_previous();
+        }
+        for _ in 0..ticks {
+            tank.advance_frame();
+            if let Some(recording) = &mut recorder {
+                match recording.capture(&tank) {
+                    Ok(true) => (),
+                    Ok(false) => message = Some((stop_recording(&mut recorder), Instant::now())),
+                    Err(error) => {
+                        recorder = None;
+                        message = Some((format!("recording failed: {}", error), Instant::now()));
+                    }
+                }
+            }
+        }
+
+        renderer.update(&tank);
+        if settings.interpolate {
+            renderer.draw_interpolated(view.origin.x, view.origin.y, view.scale(), playback.progress());
+        } else {
+            renderer.draw(view.origin.x, view.origin.y, view.scale());
         }
+        draw_preview(&preview, toolbar.selected, &view);
+        if let Some((start, end)) = selection {
+            draw_selection(start, end, &view);
+        }
+        if let (Some(copied), Some(cell), true) = (&clipboard, cursor_cell, pasting) {
+            draw_clipboard(copied, cell, &view);
+        }
+        if let (Some(cell), true) = (cursor_cell, toolbar.tool.uses_brush()) {
+            draw_brush_outline(&brush, &view, cell);
+        }
+        toolbar.draw(screen_size.x);
+        let mut status = format!(
+            "{}  {:?}  size {}  density {:.0}%  scatter {}  |  {}",
+            toolbar.tool.name(), brush.shape, brush.size, brush.density * 100.0, brush.scatter, playback.label()
+        );
+        if let (Some(copied), true) = (&clipboard, pasting) {
+            status = format!("{}  |  pasting {}x{}", status, copied.width(), copied.height());
+        }
+        if let Some(recording) = &recorder {
+            status = format!("{}  |  REC {}", status, recording.frames());
+        }
+        if let Some((text, shown_at)) = &message {
+            if shown_at.elapsed() < Duration::from_secs(3) {
+                status = format!("{}  |  {}", status, text);
+            }
+        }
+        toolbar::draw_status(&status, screen_size);
 
-        if is_key_down(KeyCode::Semicolon) {
-            let screenshot: Image = get_screen_data();
-            screenshot.export_png("my_screenshot.png");
+        // F8 starts and stops recording every simulated frame of the tank
+        if is_key_pressed(KeyCode::F8) {
+            let text = if recorder.is_some() {
+                stop_recording(&mut recorder)
+            } else {
+                let folder = std::path::Path::new(&settings.screenshot_dir);
+                match Recorder::start(folder, settings.record_format, &tank, settings.screenshot_scale, settings.record_frame_skip, settings.record_max_frames, settings.tick_rate) {
+                    Ok(recording) => {
+                        let text = format!("recording to {}", recording.path().display());
+                        recorder = Some(recording);
+                        text
+                    },
+                    Err(error) => format!("could not start recording: {}", error)
+                }
+            };
+            message = Some((text, Instant::now()));
         }
 
-        tank.advance_frame();
+        // `;` saves what's in the window and `'` saves just the tank, one picture per press
+        let screenshot = if is_key_pressed(KeyCode::Semicolon) {
+            Some(take_screenshot(&settings, None))
+        } else if is_key_pressed(KeyCode::Apostrophe) {
+            Some(take_screenshot(&settings, Some(&tank)))
+        } else {
+            None
+        };
+        match screenshot {
+            Some(Ok(path)) => message = Some((format!("saved {}", path.display()), Instant::now())),
+            Some(Err(error)) => message = Some((error, Instant::now())),
+            None => ()
+        }
+
+        if let Some(frame_time) = frame_time {
+            std::thread::sleep(frame_time.saturating_sub(frame_start.elapsed()));
+        }
         next_frame().await
     }
 }
\ No newline at end of file
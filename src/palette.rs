@@ -0,0 +1,43 @@
+use crate::glass_tank::{grain_name, Cell, EMPTY};
+
+pub struct GrainType {
+    pub symbol: char,
+    pub color: [u8; 4],
+}
+
+impl GrainType {
+    pub fn name(&self) -> &'static str {
+        grain_name(self.symbol).unwrap_or("Unknown")
+    }
+}
+
+pub const GRAIN_TYPES: [GrainType; 12] = [
+    GrainType { symbol: 'P', color: [255, 109, 193, 255] },
+    GrainType { symbol: 'F', color: [255, 0, 255, 255] },
+    GrainType { symbol: 'R', color: [229, 40, 56, 255] },
+    GrainType { symbol: 'M', color: [191, 33, 56, 255] },
+    GrainType { symbol: 'O', color: [255, 160, 0, 255] },
+    GrainType { symbol: 'S', color: [211, 175, 130, 255] },
+    GrainType { symbol: 'Y', color: [252, 249, 0, 255] },
+    GrainType { symbol: 'G', color: [0, 226, 48, 255] },
+    GrainType { symbol: 'C', color: [102, 191, 255, 255] },
+    GrainType { symbol: 'B', color: [0, 119, 242, 255] },
+    GrainType { symbol: 'U', color: [198, 122, 255, 255] },
+    GrainType { symbol: 'W', color: [255, 255, 255, 255] },
+];
+
+// Grains that aren't in the palette still fall like any other, they're just drawn black
+pub const UNKNOWN_COLOR: [u8; 4] = [0, 0, 0, 255];
+pub const EMPTY_COLOR: [u8; 4] = [0, 0, 0, 0];
+
+pub fn grain_type(symbol: char) -> Option<&'static GrainType> {
+    GRAIN_TYPES.iter().find(|grain_type| grain_type.symbol == symbol)
+}
+
+pub fn color_of(cell: Cell) -> [u8; 4] {
+    if cell == EMPTY {
+        return EMPTY_COLOR;
+    }
+
+    grain_type(cell as char).map_or(UNKNOWN_COLOR, |grain_type| grain_type.color)
+}
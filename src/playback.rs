@@ -0,0 +1,141 @@
+// Slowest and fastest speeds, as powers of two. -3 runs at an eighth of the
+// tick rate and 4 at 16 times it
+const MIN_SPEED: i32 = -3;
+const MAX_SPEED: i32 = 4;
+
+// Decides how many times to advance the tank each time the window is drawn.
+// The simulation runs at a fixed number of ticks per second whatever the
+// display does, so a scene plays out the same on any monitor
+pub struct Playback {
+    pub paused: bool,
+    tick_rate: f64,
+    max_catch_up: usize,
+    speed: i32,
+    // Ticks owed but not yet run, including a fraction of the next one
+    accumulator: f64,
+    steps: usize,
+}
+
+impl Playback {
+    pub fn new(tick_rate: f64, max_catch_up: usize) -> Playback {
+        Playback { paused: false, tick_rate, max_catch_up: max_catch_up.max(1), speed: 0, accumulator: 0.0, steps: 0 }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    // Pauses and advances exactly one tick
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.steps += 1;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed + 1).min(MAX_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed - 1).max(MIN_SPEED);
+    }
+
+    fn speed_factor(&self) -> f64 {
+        2f64.powi(self.speed)
+    }
+
+    // Called once per render with the seconds since the last one. After a
+    // stall only max_catch_up ticks (times the speed) are run and the rest of
+    // the time is dropped, so a slow machine falls behind instead of freezing
+    pub fn frames_to_advance(&mut self, elapsed: f64) -> usize {
+        if self.paused {
+            self.accumulator = 0.0;
+            return std::mem::take(&mut self.steps);
+        }
+
+        self.steps = 0;
+        self.accumulator += elapsed * self.tick_rate * self.speed_factor();
+        let ticks = self.accumulator.floor();
+        self.accumulator -= ticks;
+
+        let limit = self.max_catch_up as f64 * self.speed_factor().max(1.0);
+        if ticks > limit {
+            return limit as usize;
+        }
+        ticks as usize
+    }
+
+    // How far through the next tick we are, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        if self.paused {
+            1.0
+        } else {
+            self.accumulator as f32
+        }
+    }
+
+    pub fn label(&self) -> String {
+        let speed = if self.speed >= 0 {
+            format!("x{}", 1 << self.speed)
+        } else {
+            format!("x1/{}", 1 << -self.speed)
+        };
+
+        if self.paused {
+            format!("paused ({})", speed)
+        } else {
+            speed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paused_only_advances_when_stepped() {
+        let mut playback = Playback::new(64.0, 4);
+        assert!(playback.frames_to_advance(1.0 / 64.0) == 1);
+
+        playback.toggle_pause();
+        assert!(playback.frames_to_advance(1.0) == 0);
+
+        playback.step();
+        playback.step();
+        assert!(playback.frames_to_advance(1.0 / 64.0) == 2);
+        assert!(playback.frames_to_advance(1.0 / 64.0) == 0);
+        assert!(playback.paused);
+    }
+
+    #[test]
+    fn test_tick_count_does_not_depend_on_the_render_rate() {
+        let mut slow = Playback::new(64.0, 4);
+        let mut fast = Playback::new(64.0, 4);
+        let slow_ticks: usize = (0..32).map(|_| slow.frames_to_advance(1.0 / 32.0)).sum();
+        let fast_ticks: usize = (0..256).map(|_| fast.frames_to_advance(1.0 / 256.0)).sum();
+        assert!(slow_ticks == 64 && fast_ticks == 64, "expected 64 ticks, got {} and {}", slow_ticks, fast_ticks);
+    }
+
+    #[test]
+    fn test_catching_up_is_limited() {
+        let mut playback = Playback::new(64.0, 4);
+        assert!(playback.frames_to_advance(2.0) == 4);
+        assert!(playback.frames_to_advance(1.0 / 64.0) == 1, "time past the limit should be dropped");
+    }
+
+    #[test]
+    fn test_speed_scales_the_tick_rate() {
+        let mut playback = Playback::new(64.0, 4);
+        playback.faster();
+        playback.faster();
+        assert!(playback.frames_to_advance(1.0 / 64.0) == 4);
+        assert!(playback.label() == "x4");
+
+        for _ in 0..10 {
+            playback.slower();
+        }
+        assert!(playback.label() == "x1/8");
+        let ticks: usize = (0..16).map(|_| playback.frames_to_advance(1.0 / 64.0)).sum();
+        assert!(ticks == 2, "expected 2 ticks in 16 renders, got {}", ticks);
+    }
+}
@@ -0,0 +1,234 @@
+use crate::capture;
+use crate::glass_tank::{Cell, Tank, EMPTY};
+use crate::palette::{GRAIN_TYPES, UNKNOWN_COLOR};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    Gif,
+    // A folder of numbered PNG files, for putting together in a video editor
+    PngSequence,
+}
+
+impl std::str::FromStr for RecordFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<RecordFormat, String> {
+        match text {
+            "gif" => Ok(RecordFormat::Gif),
+            "png" => Ok(RecordFormat::PngSequence),
+            _ => Err(format!("unknown recording format '{}'", text))
+        }
+    }
+}
+
+enum Output {
+    Gif(gif::Encoder<BufWriter<File>>),
+    PngSequence,
+}
+
+// Records every simulated frame of a tank, drawn straight from its cells so it
+// doesn't need a window. Frames are written out as they come in
+pub struct Recorder {
+    output: Output,
+    path: PathBuf,
+    // GIF palette index of every cell value
+    indices: [u8; 256],
+    width: usize,
+    height: usize,
+    scale: usize,
+    // Only every (frame_skip + 1)th frame is kept
+    frame_skip: usize,
+    // Recording stops by itself after this many frames, 0 for no limit
+    max_frames: usize,
+    // Hundredths of a second each GIF frame is shown for
+    delay: u16,
+    ticks: usize,
+    frames: usize,
+}
+
+impl Recorder {
+    // Starts a new recording in the next free `recording-0001.gif` or `recording-0001` folder
+    pub fn start(folder: &Path, format: RecordFormat, tank: &Tank, scale: usize, frame_skip: usize, max_frames: usize, tick_rate: f64) -> io::Result<Recorder> {
+        std::fs::create_dir_all(folder)?;
+        let scale = scale.max(1);
+        let (width, height) = (tank.width() * scale, tank.height() * scale);
+
+        // Color 0 is the background and every grain type gets its own color after it
+        let mut palette = rgb(capture::BACKGROUND).to_vec();
+        let mut indices = [GRAIN_TYPES.len() as u8 + 1; 256];
+        indices[EMPTY as usize] = 0;
+        for (i, grain_type) in GRAIN_TYPES.iter().enumerate() {
+            palette.extend(rgb(grain_type.color));
+            indices[grain_type.symbol as usize] = i as u8 + 1;
+        }
+        palette.extend(rgb(UNKNOWN_COLOR));
+
+        let (output, path) = match format {
+            RecordFormat::Gif => {
+                if width > u16::MAX as usize || height > u16::MAX as usize {
+                    return Err(io::Error::other(format!("{}x{} is too big for a GIF", width, height)));
+                }
+
+                let path = capture::numbered_path(folder, "recording", "gif");
+                let mut encoder = gif::Encoder::new(BufWriter::new(File::create(&path)?), width as u16, height as u16, &palette).map_err(io::Error::other)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+                (Output::Gif(encoder), path)
+            },
+            RecordFormat::PngSequence => {
+                let path = capture::numbered_path(folder, "recording", "");
+                std::fs::create_dir_all(&path)?;
+                (Output::PngSequence, path)
+            }
+        };
+
+        let delay = (100.0 * (frame_skip + 1) as f64 / tick_rate).round().clamp(2.0, u16::MAX as f64) as u16;
+        Ok(Recorder { output, path, indices, width, height, scale, frame_skip, max_frames, delay, ticks: 0, frames: 0 })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    // Called after every simulated frame. Returns false once the recording is
+    // full, or if the tank changed size and can't be added to it any more
+    pub fn capture(&mut self, tank: &Tank) -> io::Result<bool> {
+        if (tank.width() * self.scale, tank.height() * self.scale) != (self.width, self.height) {
+            return Ok(false);
+        }
+        if self.max_frames > 0 && self.frames >= self.max_frames {
+            return Ok(false);
+        }
+
+        let tick = self.ticks;
+        self.ticks += 1;
+        if !tick.is_multiple_of(self.frame_skip + 1) {
+            return Ok(true);
+        }
+
+        match &mut self.output {
+            Output::Gif(encoder) => {
+                let frame = gif::Frame {
+                    width: self.width as u16,
+                    height: self.height as u16,
+                    delay: self.delay,
+                    buffer: Cow::Owned(scaled_indices(tank, self.scale, &self.indices)),
+                    ..gif::Frame::default()
+                };
+                encoder.write_frame(&frame).map_err(io::Error::other)?;
+            },
+            Output::PngSequence => {
+                let path = self.path.join(format!("frame-{:05}.png", self.frames + 1));
+                capture::write_png(&path, self.width, self.height, &capture::tank_pixels(tank, self.scale))?;
+            }
+        }
+        self.frames += 1;
+        Ok(self.max_frames == 0 || self.frames < self.max_frames)
+    }
+
+    pub fn finish(self) -> io::Result<PathBuf> {
+        if let Output::Gif(encoder) = self.output {
+            encoder.into_inner()?.into_inner().map_err(|error| error.into_error())?;
+        }
+        Ok(self.path)
+    }
+}
+
+fn rgb(color: [u8; 4]) -> [u8; 3] {
+    [color[0], color[1], color[2]]
+}
+
+fn scaled_indices(tank: &Tank, scale: usize, indices: &[u8; 256]) -> Vec<u8> {
+    let row_length = tank.width() * scale;
+    let mut frame = Vec::with_capacity(row_length * tank.height() * scale);
+    for row in tank.rows() {
+        let start = frame.len();
+        for cell in row.iter().map(|cell: &Cell| indices[*cell as usize]) {
+            frame.extend(std::iter::repeat_n(cell, scale));
+        }
+        for _ in 1..scale {
+            frame.extend_from_within(start..start + row_length);
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_folder;
+
+    #[test]
+    fn test_png_sequence_keeps_every_other_frame_up_to_the_limit() {
+        let folder = scratch_folder("png_sequence");
+        let mut t = Tank::new(4, 4, 0);
+        t.drop_sand_in_column(1, 'S');
+
+        let mut recorder = Recorder::start(&folder, RecordFormat::PngSequence, &t, 2, 1, 3, 60.0).unwrap();
+        let mut captured = 0;
+        while recorder.capture(&t).unwrap() {
+            t.advance_frame();
+            captured += 1;
+        }
+        let path = recorder.finish().unwrap();
+
+        assert!(captured == 4, "expected 4 frames offered before the limit, got {}", captured);
+        assert!(path == folder.join("recording-0001"));
+        let mut files: Vec<String> = std::fs::read_dir(&path).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+        files.sort();
+        assert!(files == ["frame-00001.png", "frame-00002.png", "frame-00003.png"], "unexpected files {:?}", files);
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_gif_recording() {
+        let folder = scratch_folder("gif");
+        let mut t = Tank::new(3, 3, 0);
+        t.drop_sand_in_column(1, 'S');
+
+        let mut recorder = Recorder::start(&folder, RecordFormat::Gif, &t, 1, 0, 0, 50.0).unwrap();
+        for _ in 0..3 {
+            assert!(recorder.capture(&t).unwrap());
+            t.advance_frame();
+        }
+        assert!(recorder.frames() == 3);
+        let file = File::open(recorder.finish().unwrap()).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(file).unwrap();
+        assert!((decoder.width(), decoder.height()) == (3, 3));
+
+        // The grain falls one row a frame. Each frame shows for 2/100 of a second at 50 ticks a second
+        let sand = GRAIN_TYPES.iter().position(|grain_type| grain_type.symbol == 'S').unwrap() as u8 + 1;
+        for row in 0..3 {
+            let frame = decoder.read_next_frame().unwrap().expect("missing frame");
+            let mut expected = vec![0; 9];
+            expected[row * 3 + 1] = sand;
+            assert!(frame.delay == 2);
+            assert!(*frame.buffer == expected, "frame {} decoded as {:?}", row, frame.buffer);
+        }
+        assert!(decoder.read_next_frame().unwrap().is_none());
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_recording_stops_when_the_tank_is_resized() {
+        let folder = scratch_folder("resized");
+        let mut t = Tank::new(3, 3, 0);
+        let mut recorder = Recorder::start(&folder, RecordFormat::Gif, &t, 1, 0, 0, 60.0).unwrap();
+        assert!(recorder.capture(&t).unwrap());
+
+        t.resize(4, 3, crate::glass_tank::Anchor::Bottom);
+        assert!(!recorder.capture(&t).unwrap());
+        recorder.finish().unwrap();
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
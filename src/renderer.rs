@@ -0,0 +1,114 @@
+use macroquad::prelude::*;
+use falling_sand::{color_of, Cell, Tank, EMPTY};
+
+// Keeps the tank in a texture with one pixel per cell, so drawing it is a
+// single textured quad no matter how many grains there are
+pub struct TankRenderer {
+    image: Image,
+    texture: Texture2D,
+    // The texture as it was before the last tick, for fading between the two
+    previous: Option<Texture2D>,
+    // Cells as they were when the texture was last uploaded
+    drawn: Vec<Cell>,
+    colors: [[u8; 4]; 256],
+}
+
+impl TankRenderer {
+    pub fn new(tank: &Tank) -> TankRenderer {
+        let image = Image::gen_image_color(tank.width() as u16, tank.height() as u16, BLANK);
+        let texture = Texture2D::from_image(&image);
+        texture.set_filter(FilterMode::Nearest);
+
+        let mut colors = [[0; 4]; 256];
+        for (cell, color) in colors.iter_mut().enumerate() {
+            *color = color_of(cell as Cell);
+        }
+
+        let mut renderer = TankRenderer {
+            image,
+            texture,
+            previous: None,
+            drawn: vec![EMPTY; tank.width() * tank.height()],
+            colors,
+        };
+        renderer.redraw_all(tank);
+        renderer
+    }
+
+    // Rewrites the rows that changed since the last call and uploads just that band of the texture
+    pub fn update(&mut self, tank: &Tank) {
+        let width = tank.width();
+        let mut changed_rows: Option<(usize, usize)> = None;
+
+        for (row, cells) in tank.rows().enumerate() {
+            let drawn = &mut self.drawn[row * width..(row + 1) * width];
+            if cells == drawn {
+                continue;
+            }
+
+            drawn.copy_from_slice(cells);
+            let pixels = self.image.get_image_data_mut();
+            for (column, cell) in cells.iter().enumerate() {
+                pixels[row * width + column] = self.colors[*cell as usize];
+            }
+
+            changed_rows = match changed_rows {
+                Some((first, _)) => Some((first, row)),
+                None => Some((row, row)),
+            };
+        }
+
+        if let Some((first, last)) = changed_rows {
+            let band = Image {
+                bytes: self.image.bytes[first * width * 4..(last + 1) * width * 4].to_vec(),
+                width: width as u16,
+                height: (last + 1 - first) as u16,
+            };
+            self.texture.update_part(&band, 0, first as i32, width as i32, band.height as i32);
+        }
+    }
+
+    fn redraw_all(&mut self, tank: &Tank) {
+        let width = tank.width();
+        let pixels = self.image.get_image_data_mut();
+        for (row, cells) in tank.rows().enumerate() {
+            self.drawn[row * width..(row + 1) * width].copy_from_slice(cells);
+            for (column, cell) in cells.iter().enumerate() {
+                pixels[row * width + column] = self.colors[*cell as usize];
+            }
+        }
+        self.texture.update(&self.image);
+    }
+
+    // Holds on to what's on screen now, before the tank is advanced
+    pub fn keep_previous(&mut self) {
+        match &self.previous {
+            Some(previous) => previous.update(&self.image),
+            None => {
+                let previous = Texture2D::from_image(&self.image);
+                previous.set_filter(FilterMode::Nearest);
+                self.previous = Some(previous);
+            }
+        }
+    }
+
+    pub fn draw(&self, x: f32, y: f32, cell_size: f32) {
+        draw_texture_ex(&self.texture, x, y, WHITE, DrawTextureParams {
+            dest_size: Some(vec2(self.texture.width() * cell_size, self.texture.height() * cell_size)),
+            ..Default::default()
+        });
+    }
+
+    // Draws the tank with the grains from before the last tick fading out
+    // over it, `progress` of the way to the next tick. Grains that didn't move
+    // are drawn over themselves so they don't flicker
+    pub fn draw_interpolated(&self, x: f32, y: f32, cell_size: f32, progress: f32) {
+        self.draw(x, y, cell_size);
+        if let Some(previous) = &self.previous {
+            draw_texture_ex(previous, x, y, Color::new(1.0, 1.0, 1.0, 1.0 - progress.clamp(0.0, 1.0)), DrawTextureParams {
+                dest_size: Some(vec2(previous.width() * cell_size, previous.height() * cell_size)),
+                ..Default::default()
+            });
+        }
+    }
+}
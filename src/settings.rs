@@ -0,0 +1,321 @@
+use falling_sand::{RecordFormat, MAX_TANK_SIDE};
+use std::collections::BTreeMap;
+use std::fmt;
+use toml::Spanned;
+
+pub const USAGE: &str = "\
+Usage: falling_sand [OPTIONS]
+
+Options:
+  --config <PATH>       Read settings from a TOML file, command line options win over it
+  --width <CELLS>       Tank width in cells [default: 150]
+  --height <CELLS>      Tank height in cells [default: 200]
+  --step <N>            How steep a pile has to be before grains slide off it [default: 0]
+  --brush-size <CELLS>  Starting width of the brush [default: 10]
+  --frame-rate <FPS>    Frames per second to cap at, 0 for as fast as the display allows [default: 0]
+  --tick-rate <TPS>     Simulation ticks per second, whatever the frame rate [default: 60]
+  --max-catch-up <N>    Most ticks run in one frame after a stall, the rest are skipped [default: 4]
+  --interpolate <BOOL>  Fade grains between their old and new cells between ticks [default: false]
+  --cell-size <PIXELS>  Size of one cell on screen before zooming [default: 4]
+  --threads <N>         Update the tank on this many threads [default: 1]
+  --scene <PATH>        Start from a saved tank instead of an empty one
+  --image <PATH>        Start from a picture turned into grains, sized to --width and --height.
+                        The simulation starts paused, press Space to let it fall
+  --dither <BOOL>       Mix grains to get closer to colors that aren't in the palette [default: true]
+  --save-file <PATH>    Where F5 saves the tank and F9 loads it from, as text if it ends in .txt
+                        and in the smaller binary format otherwise [default: tank.txt]
+  --screenshot-dir <PATH>  Folder screenshots and recordings are saved in [default: screenshots]
+  --screenshot-scale <N>   Pixels per cell when saving or recording the tank on its own [default: 1]
+  --record-format <FORMAT> gif, or png for a folder of numbered pictures [default: gif]
+  --record-frame-skip <N>  Frames to skip between recorded ones [default: 0]
+  --record-max-frames <N>  Stop recording after this many frames, 0 for no limit [default: 600]
+  -h, --help            Print this message";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub width: usize,
+    pub height: usize,
+    pub step: usize,
+    pub brush_size: usize,
+    pub frame_rate: u32,
+    pub tick_rate: f64,
+    pub max_catch_up: usize,
+    pub interpolate: bool,
+    pub cell_size: f32,
+    pub threads: usize,
+    pub scene: Option<String>,
+    pub image: Option<String>,
+    pub dither: bool,
+    pub save_file: String,
+    pub screenshot_dir: String,
+    pub screenshot_scale: usize,
+    pub record_format: RecordFormat,
+    pub record_frame_skip: usize,
+    pub record_max_frames: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            width: 150,
+            height: 200,
+            step: 0,
+            brush_size: 10,
+            frame_rate: 0,
+            tick_rate: 60.0,
+            max_catch_up: 4,
+            interpolate: false,
+            cell_size: 4.0,
+            threads: 1,
+            scene: None,
+            image: None,
+            dither: true,
+            save_file: "tank.txt".to_string(),
+            screenshot_dir: "screenshots".to_string(),
+            screenshot_scale: 1,
+            record_format: RecordFormat::Gif,
+            record_frame_skip: 0,
+            record_max_frames: 600,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    HelpRequested,
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue { key: String, value: String },
+    Config { path: String, line: usize, message: String },
+    Io { path: String, error: std::io::Error },
+    Invalid(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::HelpRequested => write!(f, "help requested"),
+            SettingsError::UnknownOption(option) => write!(f, "unknown option '{}'", option),
+            SettingsError::MissingValue(option) => write!(f, "'{}' needs a value", option),
+            SettingsError::InvalidValue { key, value } => write!(f, "'{}' is not a valid value for {}", value, key),
+            SettingsError::Config { path, line, message } => write!(f, "{}:{}: {}", path, line, message),
+            SettingsError::Io { path, error } => write!(f, "could not read {}: {}", path, error),
+            SettingsError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    // Builds settings from the defaults, then the config file if one was
+    // given, then the rest of the command line
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Settings, SettingsError> {
+        let options = split_options(args)?;
+        let mut settings = Settings::default();
+
+        for (key, value) in options.iter() {
+            if key == "config" {
+                let text = std::fs::read_to_string(value)
+                    .map_err(|error| SettingsError::Io { path: value.clone(), error })?;
+                settings.apply_config(value, &text)?;
+            }
+        }
+
+        for (key, value) in options.iter() {
+            if key != "config" {
+                settings.set(key, value)?;
+            }
+        }
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    // Reads a TOML config file. Settings are top level keys using the same
+    // names as the command line options, with dashes or underscores
+    pub fn apply_config(&mut self, path: &str, text: &str) -> Result<(), SettingsError> {
+        let line_of = |offset: usize| text[..offset.min(text.len())].matches('\n').count() + 1;
+        let config_error = |offset: usize, message: String| SettingsError::Config { path: path.to_string(), line: line_of(offset), message };
+
+        let table: BTreeMap<String, Spanned<toml::Value>> = toml::from_str(text).map_err(|error| {
+            let offset = error.span().map_or(0, |span| span.start);
+            config_error(offset, error.message().to_string())
+        })?;
+
+        for (key, value) in table {
+            let offset = value.span().start;
+            let value = match value.into_inner() {
+                toml::Value::String(text) => text,
+                toml::Value::Integer(number) => number.to_string(),
+                toml::Value::Float(number) => number.to_string(),
+                toml::Value::Boolean(flag) => flag.to_string(),
+                _ => return Err(config_error(offset, format!("'{}' needs a string, number or true/false", key)))
+            };
+
+            let key = key.replace('_', "-");
+            self.set(&key, &value).map_err(|error| match error {
+                SettingsError::UnknownOption(_) => config_error(offset, format!("unknown setting '{}'", key)),
+                error => config_error(offset, error.to_string())
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), SettingsError> {
+        match key {
+            "width" => self.width = parse(key, value)?,
+            "height" => self.height = parse(key, value)?,
+            "step" => self.step = parse(key, value)?,
+            "brush-size" => self.brush_size = parse(key, value)?,
+            "frame-rate" => self.frame_rate = parse(key, value)?,
+            "tick-rate" => self.tick_rate = parse(key, value)?,
+            "max-catch-up" => self.max_catch_up = parse(key, value)?,
+            "interpolate" => self.interpolate = parse(key, value)?,
+            "cell-size" => self.cell_size = parse(key, value)?,
+            "threads" => self.threads = parse(key, value)?,
+            "scene" => self.scene = Some(value.to_string()),
+            "image" => self.image = Some(value.to_string()),
+            "dither" => self.dither = parse(key, value)?,
+            "save-file" => self.save_file = value.to_string(),
+            "screenshot-dir" => self.screenshot_dir = value.to_string(),
+            "screenshot-scale" => self.screenshot_scale = parse(key, value)?,
+            "record-format" => self.record_format = parse(key, value)?,
+            "record-frame-skip" => self.record_frame_skip = parse(key, value)?,
+            "record-max-frames" => self.record_max_frames = parse(key, value)?,
+            _ => return Err(SettingsError::UnknownOption(key.to_string()))
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(SettingsError::Invalid("the tank needs a width and height of at least 1".to_string()));
+        }
+        if self.width > MAX_TANK_SIDE || self.height > MAX_TANK_SIDE {
+            return Err(SettingsError::Invalid(format!("the tank can be at most {} cells on a side", MAX_TANK_SIDE)));
+        }
+        if self.brush_size == 0 {
+            return Err(SettingsError::Invalid("brush-size must be at least 1".to_string()));
+        }
+        if !self.cell_size.is_finite() || self.cell_size <= 0.0 {
+            return Err(SettingsError::Invalid("cell-size must be greater than 0".to_string()));
+        }
+        if !self.tick_rate.is_finite() || self.tick_rate <= 0.0 {
+            return Err(SettingsError::Invalid("tick-rate must be greater than 0".to_string()));
+        }
+        if self.max_catch_up == 0 {
+            return Err(SettingsError::Invalid("max-catch-up must be at least 1".to_string()));
+        }
+        if self.threads == 0 {
+            return Err(SettingsError::Invalid("threads must be at least 1".to_string()));
+        }
+        if self.screenshot_scale == 0 {
+            return Err(SettingsError::Invalid("screenshot-scale must be at least 1".to_string()));
+        }
+        if self.scene.is_some() && self.image.is_some() {
+            return Err(SettingsError::Invalid("scene and image can't both be used".to_string()));
+        }
+        Ok(())
+    }
+}
+
+// Turns `--key value` and `--key=value` pairs into (key, value) in order
+fn split_options(args: impl IntoIterator<Item = String>) -> Result<Vec<(String, String)>, SettingsError> {
+    let mut options = vec![];
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Err(SettingsError::HelpRequested);
+        }
+
+        let Some(option) = arg.strip_prefix("--") else {
+            return Err(SettingsError::UnknownOption(arg));
+        };
+
+        match option.split_once('=') {
+            Some((key, value)) => options.push((key.to_string(), value.to_string())),
+            None => {
+                let value = args.next().ok_or_else(|| SettingsError::MissingValue(arg.clone()))?;
+                options.push((option.to_string(), value));
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, SettingsError> {
+    value.parse().map_err(|_| SettingsError::InvalidValue { key: key.to_string(), value: value.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_no_arguments_gives_defaults() {
+        let settings = Settings::from_args(vec![]).unwrap();
+        assert!(settings == Settings::default());
+    }
+
+    #[test]
+    fn test_command_line_options() {
+        let settings = Settings::from_args(args("--width 300 --height=100 --step 2 --cell-size 2.5 --scene demo.txt")).unwrap();
+        assert!(settings.width == 300 && settings.height == 100 && settings.step == 2);
+        assert!(settings.cell_size == 2.5);
+        assert!(Settings::from_args(args("--record-format png")).unwrap().record_format == RecordFormat::PngSequence);
+        assert!(settings.scene.as_deref() == Some("demo.txt"));
+    }
+
+    #[test]
+    fn test_bad_command_lines_are_errors() {
+        assert!(matches!(Settings::from_args(args("--width")), Err(SettingsError::MissingValue(_))));
+        assert!(matches!(Settings::from_args(args("--width wide")), Err(SettingsError::InvalidValue { .. })));
+        assert!(matches!(Settings::from_args(args("--colour red")), Err(SettingsError::UnknownOption(_))));
+        assert!(matches!(Settings::from_args(args("--width 0")), Err(SettingsError::Invalid(_))));
+        assert!(matches!(Settings::from_args(args("--threads 0")), Err(SettingsError::Invalid(_))));
+        assert!(matches!(Settings::from_args(args("--tick-rate 0")), Err(SettingsError::Invalid(_))));
+        assert!(matches!(Settings::from_args(args("--interpolate yes")), Err(SettingsError::InvalidValue { .. })));
+        assert!(matches!(Settings::from_args(args("--scene a.txt --image b.png")), Err(SettingsError::Invalid(_))));
+        assert!(matches!(Settings::from_args(args("--record-format avi")), Err(SettingsError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn test_config_file() {
+        let mut settings = Settings::default();
+        settings.apply_config("demo.toml", "# demo settings\nwidth = 640\nbrush_size = 3 # small\ninterpolate = true\nscene = \"scenes/#1.txt\"\n").unwrap();
+        assert!(settings.width == 640 && settings.brush_size == 3 && settings.interpolate);
+        assert!(settings.scene.as_deref() == Some("scenes/#1.txt"));
+    }
+
+    #[test]
+    fn test_config_file_is_real_toml() {
+        let mut settings = Settings::default();
+        settings.apply_config("demo.toml", "save_file = 'C:\\scenes\\a.sand'\nscreenshot-dir = \"shots\\tday \\\"one\\\"\"\ncell_size = 2.5\n").unwrap();
+        assert!(settings.save_file == "C:\\scenes\\a.sand", "unexpected {}", settings.save_file);
+        assert!(settings.screenshot_dir == "shots\tday \"one\"", "unexpected {}", settings.screenshot_dir);
+        assert!(settings.cell_size == 2.5);
+
+        let error = settings.apply_config("demo.toml", "width = 10\n[window]\nheight = 20\n").unwrap_err();
+        assert!(error.to_string() == "demo.toml:2: 'window' needs a string, number or true/false", "unexpected error {}", error);
+        let error = settings.apply_config("demo.toml", "width = [1, 2]\n").unwrap_err();
+        assert!(matches!(error, SettingsError::Config { line: 1, .. }), "unexpected error {:?}", error);
+    }
+
+    #[test]
+    fn test_config_errors_report_the_line() {
+        let mut settings = Settings::default();
+        let error = settings.apply_config("demo.toml", "width = 10\n\nheight: 20\n").unwrap_err();
+        assert!(matches!(error, SettingsError::Config { line: 3, .. }), "unexpected error {:?}", error);
+
+        let error = settings.apply_config("demo.toml", "speed = 10\n").unwrap_err();
+        assert!(error.to_string() == "demo.toml:1: unknown setting 'speed'", "unexpected error {}", error);
+    }
+}
@@ -0,0 +1,32 @@
+// Helpers shared by the test modules
+use crate::glass_tank::Tank;
+use std::path::PathBuf;
+
+// An empty folder in the temp directory for one test. The process id keeps
+// test runs going at the same time out of each other's way
+pub fn scratch_folder(name: &str) -> PathBuf {
+    let folder = std::env::temp_dir().join(format!("falling_sand_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&folder);
+    std::fs::create_dir_all(&folder).unwrap();
+    folder
+}
+
+pub fn grain_count(t: &Tank) -> usize {
+    t.grains().count()
+}
+
+// A seeded linear congruential generator, so tests pouring random sand pour
+// the same sand on every run
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random(seed)
+    }
+
+    // The low bits of each number repeat quickly, so callers use the high ones
+    pub fn next_number(&mut self) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        self.0
+    }
+}
@@ -0,0 +1,182 @@
+use macroquad::prelude::*;
+use falling_sand::{GrainType, GRAIN_TYPES};
+
+const BUTTON_WIDTH: f32 = 92.0;
+const BUTTON_HEIGHT: f32 = 22.0;
+const SWATCH_SIZE: f32 = 14.0;
+const PADDING: f32 = 4.0;
+const FONT_SIZE: f32 = 18.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Brush,
+    Replace,
+    Eraser,
+    Line,
+    Rectangle,
+    Ellipse,
+    Fill,
+    Select,
+}
+
+pub const TOOLS: [Tool; 8] = [Tool::Brush, Tool::Replace, Tool::Eraser, Tool::Line, Tool::Rectangle, Tool::Ellipse, Tool::Fill, Tool::Select];
+
+impl Tool {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Brush => "Brush",
+            Tool::Replace => "Replace",
+            Tool::Eraser => "Eraser",
+            Tool::Line => "Line",
+            Tool::Rectangle => "Rectangle",
+            Tool::Ellipse => "Ellipse",
+            Tool::Fill => "Fill",
+            Tool::Select => "Select"
+        }
+    }
+
+    // Number keys pick tools, letters are taken by the grains
+    pub fn key(self) -> KeyCode {
+        match self {
+            Tool::Brush => KeyCode::Key1,
+            Tool::Replace => KeyCode::Key2,
+            Tool::Eraser => KeyCode::Key3,
+            Tool::Line => KeyCode::Key4,
+            Tool::Rectangle => KeyCode::Key5,
+            Tool::Ellipse => KeyCode::Key6,
+            Tool::Fill => KeyCode::Key7,
+            Tool::Select => KeyCode::Key8
+        }
+    }
+
+    // Tools that paint with the brush as the mouse moves, the rest are dragged
+    // out and only touch the tank when the button is let go
+    pub fn uses_brush(self) -> bool {
+        matches!(self, Tool::Brush | Tool::Replace | Tool::Eraser)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Button {
+    Tool(Tool),
+    Grain(&'static GrainType),
+}
+
+// A bar across the top of the window with a button for every tool and grain
+// type. Buttons wrap onto more rows when the window is too narrow for them
+pub struct Toolbar {
+    pub tool: Tool,
+    pub selected: char,
+}
+
+impl Toolbar {
+    pub fn new() -> Toolbar {
+        Toolbar { tool: Tool::Brush, selected: 'S' }
+    }
+
+    fn button_count() -> usize {
+        TOOLS.len() + GRAIN_TYPES.len()
+    }
+
+    fn buttons_per_row(screen_width: f32) -> usize {
+        (((screen_width - PADDING) / (BUTTON_WIDTH + PADDING)) as usize).max(1)
+    }
+
+    pub fn height(screen_width: f32) -> f32 {
+        let rows = Toolbar::button_count().div_ceil(Toolbar::buttons_per_row(screen_width));
+        PADDING + rows as f32 * (BUTTON_HEIGHT + PADDING)
+    }
+
+    // The part of the window left over for the tank
+    pub fn tank_area(&self, screen_size: Vec2) -> Rect {
+        let height = Toolbar::height(screen_size.x);
+        Rect::new(0.0, height, screen_size.x, (screen_size.y - height).max(0.0))
+    }
+
+    fn buttons(&self, screen_width: f32) -> impl Iterator<Item = (Rect, Button)> {
+        let per_row = Toolbar::buttons_per_row(screen_width);
+        let tools = TOOLS.iter().map(|tool| Button::Tool(*tool));
+        let grains = GRAIN_TYPES.iter().map(Button::Grain);
+        tools.chain(grains).enumerate().map(move |(i, button)| {
+            let x = PADDING + (i % per_row) as f32 * (BUTTON_WIDTH + PADDING);
+            let y = PADDING + (i / per_row) as f32 * (BUTTON_HEIGHT + PADDING);
+            (Rect::new(x, y, BUTTON_WIDTH, BUTTON_HEIGHT), button)
+        })
+    }
+
+    pub fn handle_keys(&mut self) {
+        if let Some(tool) = TOOLS.iter().find(|tool| is_key_pressed(tool.key())) {
+            self.tool = *tool;
+        }
+    }
+
+    // Picks whichever tool or grain was clicked on. Returns true if the mouse
+    // is over the bar, so the click doesn't also paint on the tank behind it
+    pub fn handle_mouse(&mut self, mouse: Vec2, screen_width: f32) -> bool {
+        if mouse.y >= Toolbar::height(screen_width) {
+            return false;
+        }
+
+        if is_mouse_button_pressed(MouseButton::Left) {
+            match self.buttons(screen_width).find(|(rect, _)| rect.contains(mouse)) {
+                Some((_, Button::Tool(tool))) => self.tool = tool,
+                Some((_, Button::Grain(grain_type))) => self.selected = grain_type.symbol,
+                None => ()
+            }
+        }
+        true
+    }
+
+    pub fn draw(&self, screen_width: f32) {
+        draw_rectangle(0.0, 0.0, screen_width, Toolbar::height(screen_width), Color::from_rgba(30, 30, 30, 255));
+
+        for (rect, button) in self.buttons(screen_width) {
+            let selected = match button {
+                Button::Tool(tool) => tool == self.tool,
+                Button::Grain(grain_type) => grain_type.symbol == self.selected
+            };
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, if selected { DARKGRAY } else { Color::from_rgba(45, 45, 45, 255) });
+            if selected {
+                draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, WHITE);
+            }
+
+            match button {
+                Button::Tool(tool) => {
+                    draw_text(tool.name(), rect.x + 2.0 * PADDING, rect.y + 16.0, FONT_SIZE, WHITE);
+                },
+                Button::Grain(grain_type) => {
+                    let [r, g, b, a] = grain_type.color;
+                    let swatch_y = rect.y + (rect.h - SWATCH_SIZE) / 2.0;
+                    draw_rectangle(rect.x + PADDING, swatch_y, SWATCH_SIZE, SWATCH_SIZE, Color::from_rgba(r, g, b, a));
+                    draw_text(grain_type.name(), rect.x + SWATCH_SIZE + 2.0 * PADDING, rect.y + 16.0, FONT_SIZE, WHITE);
+                }
+            }
+        }
+    }
+}
+
+// A line of text along the bottom of the window
+pub fn draw_status(text: &str, screen_size: Vec2) {
+    let dimensions = measure_text(text, None, FONT_SIZE as u16, 1.0);
+    draw_rectangle(0.0, screen_size.y - dimensions.height - 2.0 * PADDING, dimensions.width + 2.0 * PADDING, dimensions.height + 2.0 * PADDING, Color::from_rgba(0, 0, 0, 160));
+    draw_text(text, PADDING, screen_size.y - PADDING, FONT_SIZE, WHITE);
+}
+
+// Holding a grain's letter drops that grain, like before there was a toolbar
+pub fn grain_key(symbol: char) -> Option<KeyCode> {
+    match symbol {
+        'P' => Some(KeyCode::P),
+        'F' => Some(KeyCode::F),
+        'R' => Some(KeyCode::R),
+        'M' => Some(KeyCode::M),
+        'O' => Some(KeyCode::O),
+        'S' => Some(KeyCode::S),
+        'Y' => Some(KeyCode::Y),
+        'G' => Some(KeyCode::G),
+        'C' => Some(KeyCode::C),
+        'B' => Some(KeyCode::B),
+        'U' => Some(KeyCode::U),
+        'W' => Some(KeyCode::W),
+        _ => None
+    }
+}
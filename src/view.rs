@@ -0,0 +1,88 @@
+use falling_sand::Position;
+use macroquad::math::{vec2, Rect, Vec2};
+
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 16.0;
+
+// Where the tank sits on screen. A cell is cell_size * zoom pixels across and
+// the tank's top left corner is drawn at origin
+pub struct View {
+    pub cell_size: f32,
+    pub zoom: f32,
+    pub origin: Vec2,
+}
+
+impl View {
+    pub fn new(cell_size: f32) -> View {
+        View { cell_size, zoom: 1.0, origin: vec2(0.0, 0.0) }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.cell_size * self.zoom
+    }
+
+    // Puts the tank in the middle of an area of the screen at its normal size
+    pub fn reset(&mut self, tank_size: (usize, usize), area: Rect) {
+        self.zoom = 1.0;
+        self.center(tank_size, area);
+    }
+
+    pub fn center(&mut self, tank_size: (usize, usize), area: Rect) {
+        let tank_pixels = vec2(tank_size.0 as f32, tank_size.1 as f32) * self.scale();
+        self.origin = area.point() + ((area.size() - tank_pixels) / 2.0).floor();
+    }
+
+    pub fn pan(&mut self, delta: Vec2) {
+        self.origin += delta;
+    }
+
+    // Zooms around a point on screen, keeping whatever is under it in place
+    pub fn zoom_at(&mut self, screen_point: Vec2, factor: f32) {
+        let zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let tank_point = (screen_point - self.origin) / self.scale();
+        self.zoom = zoom;
+        self.origin = screen_point - tank_point * self.scale();
+    }
+
+    // Returns the cell under a point on screen, if it's inside the tank
+    pub fn cell_at(&self, screen_point: Vec2, tank_size: (usize, usize)) -> Option<Position> {
+        let tank_point = (screen_point - self.origin) / self.scale();
+        if tank_point.x < 0.0 || tank_point.y < 0.0 {
+            return None;
+        }
+
+        let (column, row) = (tank_point.x as usize, tank_point.y as usize);
+        if column >= tank_size.0 || row >= tank_size.1 {
+            return None;
+        }
+
+        Some(Position::new(row, column))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_at_maps_through_scale_and_origin() {
+        let mut view = View::new(4.0);
+        view.origin = vec2(10.0, 20.0);
+
+        assert!(view.cell_at(vec2(10.0, 20.0), (5, 5)) == Some(Position::new(0, 0)));
+        assert!(view.cell_at(vec2(17.9, 31.0), (5, 5)) == Some(Position::new(2, 1)));
+        assert!(view.cell_at(vec2(9.0, 20.0), (5, 5)).is_none());
+        assert!(view.cell_at(vec2(30.0, 20.0), (5, 5)).is_none());
+    }
+
+    #[test]
+    fn test_zoom_at_keeps_the_point_under_the_cursor() {
+        let mut view = View::new(2.0);
+        let cursor = vec2(41.0, 33.0);
+        let before = view.cell_at(cursor, (100, 100));
+
+        view.zoom_at(cursor, 3.0);
+        assert!(view.zoom == 3.0);
+        assert!(view.cell_at(cursor, (100, 100)) == before, "expected {:?} actual {:?}", before, view.cell_at(cursor, (100, 100)));
+    }
+}